@@ -0,0 +1,791 @@
+use std::cmp::Reverse;
+
+use log::*;
+use bytesize::ByteSize;
+
+use crate::args::{AppArgs, AttachmentPolicy, BurnSubs, SubsFallback, SubsPolicy, TranscodeMode};
+use crate::ffprobe::mkv::{MkvFile, Stream};
+
+/// The knobs that decide which streams make it into the output.
+pub struct TrackFilters {
+    priority_sub_lang: String,
+    max_sub_tracks: Option<usize>,
+    subs: SubsPolicy,
+    subs_fallback: SubsFallback,
+    max_audio_tracks: Option<usize>,
+    extra_audio_langs: Vec<String>,
+    keep_commentary: bool,
+    prefer_audio_codecs: Vec<String>,
+    keep_channels: Vec<u64>,
+
+    attachments: AttachmentPolicy,
+    keep_attachment_mimes: Vec<String>,
+    smart_attachments: bool,
+    copy_unknown_streams: bool
+}
+
+impl TrackFilters {
+    pub fn new(cfg: &AppArgs) -> TrackFilters {
+        TrackFilters {
+            priority_sub_lang: cfg.priority_sub_lang(),
+            max_sub_tracks: cfg.max_sub_tracks(),
+            subs: cfg.subs(),
+            subs_fallback: cfg.subs_fallback(),
+            max_audio_tracks: cfg.max_audio_tracks(),
+            extra_audio_langs: cfg.extra_audio_langs(),
+            keep_commentary: cfg.keep_commentary(),
+            prefer_audio_codecs: cfg.prefer_audio_codecs(),
+            keep_channels: cfg.keep_channels(),
+
+            attachments: cfg.attachments(),
+            keep_attachment_mimes: cfg.keep_attachment_mimes(),
+            smart_attachments: cfg.smart_attachments(),
+            copy_unknown_streams: cfg.copy_unknown_streams()
+        }
+    }
+}
+
+/// What crunching a file would do to it: whether its video gets transcoded, and which tracks make it to the output.
+pub struct CrunchPlan<'a> {
+    pub transcode_video: bool,
+
+    pub subs: Vec<(usize, &'a Stream)>,
+    pub audio: Vec<(usize, &'a Stream)>,
+    pub attachments: Vec<(usize, &'a Stream)>,
+    pub others: Vec<&'a Stream>
+}
+
+impl CrunchPlan<'_> {
+    /// Whether any of `mkv`'s tracks would be left out of the output.
+    pub fn drops_tracks(&self, mkv: &MkvFile) -> bool {
+        self.subs.len() != mkv.subtitles_streams().len()
+            || self.audio.len() != mkv.audio_streams().len()
+            || self.attachments.len() != mkv.attachments().len()
+            || self.others.len() != mkv.other_streams().len()
+    }
+}
+
+/// Runs every analysis on `mkv`, without touching the file. Doesn't account for per-run options like --burn-subs.
+pub fn needs_crunching<'a>(mkv: &'a MkvFile, filters: &TrackFilters, transcode_mode: TranscodeMode) -> CrunchPlan<'a> {
+    let transcode_video = match transcode_mode {
+        TranscodeMode::Auto => analyze_video(mkv),
+        TranscodeMode::Force => mkv.primary_video_stream().is_some(),
+        TranscodeMode::Never => false
+    };
+
+    let subs = analyze_sub_tracks(mkv, filters);
+    let audio = analyze_audio_tracks(mkv, filters);
+    let attachments = analyze_attachments(mkv, filters, &subs);
+    let others = analyze_other_streams(mkv, filters);
+
+    CrunchPlan { transcode_video, subs, audio, attachments, others }
+}
+
+pub fn analyze_video(mkv: &MkvFile) -> bool {
+    let video = match mkv.primary_video_stream() {
+        Some((_, video)) => video,
+        None => return false
+    };
+
+    let bit_depth = video.bit_depth();
+
+    // Already on the target codec, re-encoding would only lose quality no matter the size.
+    if video.codec() == TARGET_CODEC {
+        info!("  Video is already {TARGET_CODEC}, copying it.");
+        false
+    }
+    // Don't transcode stuff that's too small, will probably nuke quality.
+    else if ByteSize::b(mkv.size()) < MIN_TRANSCODE_SIZE {
+        false
+    }
+    // The encoder can't keep anything above 10-bit, leave those alone instead of truncating them.
+    else if bit_depth > 10 {
+        info!("  Video is {bit_depth}-bit, which the encoder can't preserve. Copying it instead.");
+        false
+    }
+    else {
+        true
+    }
+}
+
+pub fn analyze_sub_tracks<'a>(mkv: &'a MkvFile, filters: &TrackFilters) -> Vec<(usize, &'a Stream)> {
+    let priority_lang = filters.priority_sub_lang.as_str();
+
+    let all_streams = mkv.subtitles_streams();
+    let stream_count = all_streams.len();
+
+    if stream_count == 1 {
+        info!("  Keeping all subs ({stream_count}).");
+        
+        return all_streams
+            .into_iter()
+            .enumerate()
+            .collect()
+    }
+
+    let mut preserved_streams = match filters.subs {
+        SubsPolicy::Auto => filter_subs(all_streams, priority_lang),
+        // Main subs plus signs, going by nothing but the flags.
+        SubsPolicy::Minimal => {
+            let default_idx = all_streams.iter().position(| s | s.is_default());
+
+            all_streams
+                .into_iter()
+                .enumerate()
+                .filter(| (idx, s) | Some(*idx) == default_idx || s.is_forced())
+                .collect()
+        }
+    };
+
+    // Like with audio, some subs beat ending up with a file nobody can follow.
+    if preserved_streams.is_empty() && stream_count > 0 {
+        let all_streams = mkv.subtitles_streams().into_iter().enumerate();
+
+        match filters.subs_fallback {
+            SubsFallback::None => {}
+            SubsFallback::KeepAll => {
+                info!("  Every subtitle track got filtered out, keeping all of them instead.");
+                preserved_streams = all_streams.collect();
+            }
+            SubsFallback::KeepFirst => {
+                info!("  Every subtitle track got filtered out, keeping the first one instead.");
+                preserved_streams = all_streams.take(1).collect();
+            }
+        }
+    }
+
+    preserved_streams = cap_tracks(preserved_streams, filters.max_sub_tracks, priority_lang);
+
+    if preserved_streams.len() < stream_count {
+        info!("  Keeping {}/{} subs.", preserved_streams.len(), stream_count);
+
+        for (_, s) in preserved_streams.iter() {
+            let stream_title = s.stream_title();
+            
+            let stream_name = {
+                if stream_title.is_empty() {
+                    "Untitled track"
+                }
+                else {
+                    stream_title.as_str()
+                }
+            };
+
+            info!("      {stream_name} ({})", s.codec());
+        }
+    }
+    else {
+        info!("  Keeping all subs ({stream_count}).");
+    }
+
+    preserved_streams
+}
+
+pub fn analyze_audio_tracks<'a>(mkv: &'a MkvFile, filters: &TrackFilters) -> Vec<(usize, &'a Stream)> {
+    let all_streams = mkv.audio_streams();
+    let stream_count = all_streams.len();
+
+    if stream_count == 1 {
+        info!("  Keeping all audio tracks ({stream_count}).");
+
+        return all_streams
+            .into_iter()
+            .enumerate()
+            .collect()
+    }
+
+    let is_extra_lang = | s: &Stream | filters.extra_audio_langs.iter().any(| lang | *lang == s.stream_language());
+
+    let mut preserved_streams: Vec<(usize, &Stream)> = all_streams
+        .into_iter()
+        .enumerate()
+        // Filter non-japanese, leave undefined just in case.
+        .filter(| (_, s) | {
+            let l = s.stream_language();
+            l.is_empty() || l == "jpn" || l == "chi" || l == "und" || is_extra_lang(s)
+        })
+        // Fallback filter + nuke commentary tracks.
+        .filter(| (_, s) | {
+            let stream_name = s.stream_title().to_lowercase();
+            // Plenty of files only flag commentary through the disposition, with a plain title.
+            let is_commentary = s.is_comment() || stream_name.contains("commentary");
+            // Asked-for languages get to say so in their title.
+            let is_english = !is_extra_lang(s) && stream_name.contains("eng") && stream_name.contains("english");
+
+            (filters.keep_commentary || !is_commentary) && !stream_name.contains("description") && !is_english
+        })
+        .collect()
+    ;
+
+    // Among tracks in the same language, keep the ones with the most preferred codec.
+    // Goes before the stereo check, asking for flac should get flac even if it's 5.1.
+    if preserved_streams.len() > 1 && !filters.prefer_audio_codecs.is_empty() {
+        let codec_rank = | s: &Stream | {
+            filters.prefer_audio_codecs.iter().position(| codec | codec.eq_ignore_ascii_case(s.codec()))
+        };
+
+        let candidates = preserved_streams.clone();
+
+        preserved_streams.retain(| (_, s) | {
+            let best_rank = candidates.iter()
+                .filter(| (_, other) | other.stream_language() == s.stream_language())
+                .filter_map(| (_, other) | codec_rank(other))
+                .min()
+            ;
+
+            match best_rank {
+                Some(best_rank) => codec_rank(s) == Some(best_rank),
+                None => true
+            }
+        });
+    }
+
+    // Try to nuke potential 5.1 tracks if we still have more than one track.
+    if preserved_streams.len() > 1 {
+        let jpn_stereo: Vec<(usize, &Stream)> = preserved_streams.clone()
+            .into_iter()
+            .filter( | (_, s) | {
+                // == 0 is a fallback in case parsing drops the ball.
+                filters.keep_channels.contains(&s.channels()) || s.channels() == 0
+            })
+            .collect()
+        ;
+
+        if !jpn_stereo.is_empty() {
+            preserved_streams = jpn_stereo;
+        }
+    }
+
+    preserved_streams = cap_tracks(preserved_streams, filters.max_audio_tracks, "jpn");
+
+    if preserved_streams.len() < stream_count {
+        info!("  Keeping {}/{stream_count} audio tracks.", preserved_streams.len());
+
+        for (_, s) in preserved_streams.iter() {
+            let stream_title = s.stream_title();
+
+            let stream_name = {
+                if stream_title.is_empty() {
+                    "Untitled track"
+                }
+                else {
+                    stream_title.as_str()
+                }
+            };
+
+            info!("      {stream_name} ({})", s.codec());
+        }
+    }
+    else {
+        info!("  Keeping all audio tracks ({stream_count}).");
+    }
+
+    preserved_streams
+}
+
+pub fn analyze_attachments<'a>(mkv: &'a MkvFile, filters: &TrackFilters, kept_subs: &[(usize, &Stream)]) -> Vec<(usize, &'a Stream)> {
+    let extra_mimes = &filters.keep_attachment_mimes;
+    // Fonts are dead weight without any ASS subs around to use them.
+    let keep_fonts = !filters.smart_attachments || kept_subs.iter().any(| (_, s) | SSA_CODECS.contains(&s.codec()));
+
+    let all_attachments = mkv.attachments();
+    let attachment_count = all_attachments.len();
+
+    if filters.attachments == AttachmentPolicy::None {
+        if attachment_count > 0 {
+            info!("  Dropping all attachments ({attachment_count}).");
+        }
+
+        return Vec::new();
+    }
+
+    if !keep_fonts && attachment_count > 0 && filters.attachments == AttachmentPolicy::Fonts {
+        info!("  No ASS subs are being kept, dropping fonts.");
+    }
+
+    let mut preserved_attachments: Vec<(usize, &Stream)> = all_attachments
+        .into_iter()
+        .enumerate()
+        .filter(| (_, a) | {
+            if filters.attachments == AttachmentPolicy::All {
+                return true;
+            }
+
+            let mime_type = a.mime_type().to_lowercase();
+
+            // Some muxers don't bother with a proper MIME type, so go by the filename on those.
+            let is_font = {
+                if mime_type.is_empty() || mime_type == GENERIC_MIME_TYPE {
+                    let name = a.stream_title().to_lowercase();
+                    // Preserve fonts and files without extensions.
+                    name.contains("ttf") || name.contains("ttc") || name.contains("otf") || !name.contains('.')
+                }
+                else {
+                    FONT_MIME_TYPES.contains(&mime_type.as_str())
+                }
+            };
+
+            (is_font && keep_fonts) || extra_mimes.iter().any(| m | m.to_lowercase() == mime_type)
+        })
+        .collect()
+    ;
+
+    let preserved = preserved_attachments.len();
+
+    preserved_attachments.sort_unstable_by_key(| (_, a) | a.stream_title());
+    preserved_attachments.dedup_by_key(| (_, a) | a.stream_title());
+
+    if preserved < attachment_count {
+        info!("  Keeping {preserved}/{attachment_count} attachments.");
+    }
+    else {
+        info!("  Keeping all attachments ({attachment_count}).");
+    }
+
+    preserved_attachments
+}
+
+/// The subtitle track to burn into the video. `auto` only looks at the tracks that made it through the filters.
+pub fn pick_burned_sub<'a>(mkv: &'a MkvFile, setting: BurnSubs, kept_subs: &[(usize, &'a Stream)]) -> Option<(usize, &'a Stream)> {
+    let picked = match setting {
+        BurnSubs::Track(idx) => {
+            let picked = mkv.subtitles_streams().get(idx).copied().map(| s | (idx, s));
+
+            if picked.is_none() {
+                warn!("  There's no subtitle track {idx} to burn in.");
+            }
+
+            picked
+        }
+        BurnSubs::Auto => {
+            let is_dialogue = | s: &Stream | {
+                let title = s.stream_title().to_lowercase();
+                !IMAGE_SUB_CODECS.contains(&s.codec()) && !PARTIAL_SUB_WORDS.iter().any(| word | title.contains(word))
+            };
+
+            let picked = kept_subs.iter()
+                .find(| (_, s) | s.is_default() && is_dialogue(s))
+                .or_else(|| kept_subs.iter().find(| (_, s) | is_dialogue(s)))
+                .copied()
+            ;
+
+            if picked.is_none() && !kept_subs.is_empty() {
+                warn!("  Couldn't find a dialogue subtitle track to burn in.");
+            }
+
+            picked
+        }
+    };
+
+    // The subtitles filter only renders text, image subs would need an overlay instead.
+    picked.filter(| (idx, s) | {
+        let is_image = IMAGE_SUB_CODECS.contains(&s.codec());
+
+        if is_image {
+            warn!("  Subtitle track {idx} is image-based ({}), it can't be burned in.", s.codec());
+        }
+
+        !is_image
+    })
+}
+
+pub fn analyze_other_streams<'a>(mkv: &'a MkvFile, filters: &TrackFilters) -> Vec<&'a Stream> {
+    let all_streams = mkv.other_streams();
+    let stream_count = all_streams.len();
+
+    if stream_count == 0 {
+        return all_streams;
+    }
+
+    if filters.copy_unknown_streams {
+        info!("  Copying all unknown streams ({stream_count}).");
+        all_streams
+    }
+    else {
+        info!("  Dropping all unknown streams ({stream_count}).");
+        Vec::new()
+    }
+}
+
+/// Compares the tracks that were meant to be kept against what actually made it into the output.
+/// Returns a description of every kept track with no match on `output`.
+/// The output can have extra tracks (external subs, fonts), so every kept track is looked for instead of going by counts.
+pub fn missing_tracks(kept: &[(usize, &Stream)], output: &[&Stream]) -> Vec<String> {
+    // Titles of audio tracks get stripped and their codec might change, language (or file name) is what survives.
+    let track_key = | s: &Stream | {
+        let language = s.stream_language();
+
+        if language.is_empty() {
+            s.stream_title()
+        }
+        else {
+            language
+        }
+    };
+
+    let mut remaining: Vec<String> = output.iter().map(| s | track_key(s)).collect();
+    let mut missing = Vec::new();
+
+    for (stream_idx, stream) in kept {
+        let key = track_key(stream);
+
+        if let Some(position) = remaining.iter().position(| k | *k == key) {
+            remaining.remove(position);
+        }
+        else {
+            missing.push(format!("#{stream_idx} ({key}, {})", stream.codec()));
+        }
+    }
+
+    missing
+}
+
+/// Where on the output the untagged ("und") tracks of `kept` end up, for --default-audio-lang and --default-sub-lang.
+pub fn untagged_tracks(kept: &[(usize, &Stream)]) -> Vec<usize> {
+    kept.iter()
+        .enumerate()
+        .filter(| (_, (_, s)) | s.stream_language() == "und")
+        .map(| (idx, _) | idx)
+        .collect()
+}
+
+/// Looks for a language on a release's file name, like `[JPN]` or `.eng.`.
+/// Only returns something if the name mentions exactly one language.
+pub fn language_from_file_name(file_name: &str) -> Option<&'static str> {
+    let mut languages: Vec<&str> = file_name
+        .split(| c: char | !c.is_alphanumeric())
+        .filter_map(| token | {
+            FILE_NAME_LANGUAGE_TOKENS.iter()
+                .find(| (name, _) | token.eq_ignore_ascii_case(name))
+                .map(| (_, language) | *language)
+        })
+        .collect()
+    ;
+
+    languages.sort_unstable();
+    languages.dedup();
+
+    match languages.as_slice() {
+        [language] => Some(language),
+        _ => None
+    }
+}
+
+// The default rules: one track per title (or language), no signs/songs tracks and such, only wanted languages,
+// and only ASS when there's any.
+fn filter_subs<'a>(all_streams: Vec<&'a Stream>, priority_lang: &str) -> Vec<(usize, &'a Stream)> {
+    let mut preserved_streams: Vec<(usize, &Stream)> = all_streams
+        .into_iter()
+        .enumerate()
+        .collect()
+    ;
+
+    // Heaviest track first within each group, so the dedup keeps the full dialogue over a signs track.
+    preserved_streams.sort_unstable_by_key(|(_, s)| {
+        let key = {
+            if s.stream_title().is_empty() {
+                s.stream_language()
+            }
+            else {
+                s.stream_title()
+            }
+        };
+
+        (key, Reverse(s.subtitle_weight()))
+    });
+    preserved_streams.dedup_by_key(| (_, s) | {
+        if s.stream_title().is_empty() {
+            s.stream_language()
+        }
+        else {
+            s.stream_title()
+        }
+    });
+
+    let has_ass = preserved_streams.iter()
+        .filter(|(_, s)| s.codec() == ASS_CODEC)
+        .count() > 0
+    ;
+
+    preserved_streams
+        .into_iter()
+        // Filter out unwanted sub tracks.
+        .filter(| (_, s) | {
+            let name = s.stream_title().to_lowercase();
+
+            let title_hint = LANGUAGE_TITLE_HINTS.iter()
+                .any(| (lang, hint) | *lang == priority_lang && name.contains(hint))
+            ;
+
+            if title_hint || name.contains(priority_lang) || s.stream_language() == priority_lang {
+                true
+            }
+            else {
+                let mut keep = true;
+
+                for bad_word in BAD_SUB_WORDS {
+                    if name == bad_word || name.contains(bad_word) {
+                        keep = false;
+                        break;
+                    }
+                }
+                
+                keep
+            }
+        })
+        // Filter out unused languages.
+        .filter(| (_, s) | {
+            let language = s.stream_language();
+            OK_SUB_LANGS.contains(&language.as_str()) || language == priority_lang
+        })
+        // Filter out PGS and other formats if we have ASS subs.
+        .filter(| (_, s) | {
+            if has_ass {
+                s.codec() == ASS_CODEC || s.stream_language() == priority_lang
+            }
+            else {
+                true
+            }
+        })
+        .collect()
+}
+
+/// How many of `streams` are in `filter`'s language or codec. All of them without a filter.
+pub fn count_matching(streams: &[(usize, &Stream)], filter: Option<&str>) -> usize {
+    match filter {
+        Some(filter) => streams.iter().filter(| (_, s) | s.stream_language() == filter || s.codec().eq_ignore_ascii_case(filter)).count(),
+        None => streams.len()
+    }
+}
+
+/// Trims `streams` down to `max`, preferring default tracks and then those in `preferred_lang`.
+/// The surviving tracks keep their original order.
+fn cap_tracks<'a>(mut streams: Vec<(usize, &'a Stream)>, max: Option<usize>, preferred_lang: &str) -> Vec<(usize, &'a Stream)> {
+    let max = match max {
+        Some(max) if streams.len() > max => max,
+        _ => return streams
+    };
+
+    info!("  Capping {} tracks down to {max}.", streams.len());
+
+    let mut ranked = streams.clone();
+    ranked.sort_by_key(| (_, s) | (!s.is_default(), s.stream_language() != preferred_lang));
+    ranked.truncate(max);
+
+    streams.retain(| (idx, _) | ranked.iter().any(| (ranked_idx, _) | ranked_idx == idx));
+    streams
+}
+
+const ASS_CODEC: &str = "ass";
+const SSA_CODECS: [&str; 2] = ["ass", "ssa"];
+pub const TARGET_CODEC: &str = "av1";
+// Anything smaller than this doesn't get its video transcoded, unless forced.
+const MIN_TRANSCODE_SIZE: ByteSize = ByteSize::mib(600);
+
+// Words that give away a track's language on its title, besides the language code itself.
+const LANGUAGE_TITLE_HINTS: [(&str, &str); 5] = [
+    ("jpn", "jap"),
+    ("kor", "korean"),
+    ("chi", "chinese"),
+    ("zho", "chinese"),
+    ("spa", "spanish")
+];
+
+const OK_SUB_LANGS: [&str; 5] = [
+    "eng",
+    "enm",
+    "jpn",
+    "spa",
+    "und"
+];
+
+// Tracks that only cover part of the dialogue, no good for burning in.
+const PARTIAL_SUB_WORDS: [&str; 4] = [
+    "s&s",
+    "signs",
+    "songs",
+    "forced"
+];
+
+const IMAGE_SUB_CODECS: [&str; 3] = [
+    "hdmv_pgs_subtitle",
+    "dvd_subtitle",
+    "dvb_subtitle"
+];
+
+const BAD_SUB_WORDS: [&str; 8] = [
+    "s&s",
+    "signs",
+    "songs",
+    "spain",
+    "closed",
+    "captions",
+    "closed captions",
+    "commentary"
+];
+
+// Short codes are left out on purpose, "en" or "es" show up in plenty of titles.
+const FILE_NAME_LANGUAGE_TOKENS: [(&str, &str); 10] = [
+    ("jpn", "jpn"),
+    ("japanese", "jpn"),
+    ("eng", "eng"),
+    ("english", "eng"),
+    ("spa", "spa"),
+    ("spanish", "spa"),
+    ("kor", "kor"),
+    ("korean", "kor"),
+    ("chi", "chi"),
+    ("chinese", "chi")
+];
+
+const GENERIC_MIME_TYPE: &str = "application/octet-stream";
+
+const FONT_MIME_TYPES: [&str; 10] = [
+    "font/otf",
+    "font/ttf",
+    "font/sfnt",
+    "font/collection",
+    "application/font-sfnt",
+    "application/x-font-otf",
+    "application/x-font-ttf",
+    "application/x-truetype-font",
+    "application/vnd.ms-opentype",
+    "application/x-font-opentype"
+];
+
+pub const LOSSLESS_AUDIO_CODECS: [&str; 4] = [
+    "dts",
+    "flac",
+    "truehd",
+    "pcm_s24le"
+];
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::ffprobe::mkv::CodecType;
+
+    fn filters(extra_args: &[&str]) -> TrackFilters {
+        let args = ["mkv_cruncher", "--input-dir", "in", "--output-dir", "out"].iter().chain(extra_args);
+        TrackFilters::new(&AppArgs::parse_from(args))
+    }
+
+    fn audio(index: usize, language: &str, title: &str, channels: u64) -> Stream {
+        let codec_type = CodecType::Audio { language: language.to_owned(), title: title.to_owned(), channels };
+        Stream::new(index, "aac", codec_type)
+    }
+
+    fn sub(index: usize, language: &str) -> Stream {
+        let codec_type = CodecType::Subtitle { language: language.to_owned(), title: String::new(), event_count: None, byte_count: None };
+        Stream::new(index, "ass", codec_type)
+    }
+
+    fn attachment(index: usize, filename: &str, mime_type: &str) -> Stream {
+        let codec_type = CodecType::Attachment { filename: filename.to_owned(), mime_type: mime_type.to_owned() };
+        Stream::new(index, "", codec_type)
+    }
+
+    fn kept_indices(kept: &[(usize, &Stream)]) -> Vec<usize> {
+        kept.iter().map(| (idx, _) | *idx).collect()
+    }
+
+    #[test]
+    fn small_target_codec_is_copied() {
+        let mkv = MkvFile::new(ByteSize::mib(300).as_u64(), 0.0, vec![Stream::video(0, TARGET_CODEC, "yuv420p10le", None)]);
+        assert!(!analyze_video(&mkv));
+    }
+
+    #[test]
+    fn large_target_codec_is_copied() {
+        let mkv = MkvFile::new(ByteSize::gib(4).as_u64(), 0.0, vec![Stream::video(0, TARGET_CODEC, "yuv420p10le", None)]);
+        assert!(!analyze_video(&mkv));
+    }
+
+    #[test]
+    fn small_other_codec_is_copied() {
+        let mkv = MkvFile::new(ByteSize::mib(300).as_u64(), 0.0, vec![Stream::video(0, "hevc", "yuv420p10le", None)]);
+        assert!(!analyze_video(&mkv));
+    }
+
+    #[test]
+    fn large_other_codec_is_transcoded() {
+        let mkv = MkvFile::new(ByteSize::gib(4).as_u64(), 0.0, vec![Stream::video(0, "hevc", "yuv420p10le", None)]);
+        assert!(analyze_video(&mkv));
+    }
+
+    #[test]
+    fn commentary_and_surround_leave_the_stereo_track() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            audio(0, "jpn", "Commentary", 2),
+            audio(1, "jpn", "", 6),
+            audio(2, "jpn", "", 2)
+        ]);
+
+        assert_eq!(kept_indices(&analyze_audio_tracks(&mkv, &filters(&[]))), vec![2]);
+    }
+
+    #[test]
+    fn keep_commentary_keeps_both_stereo_tracks() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            audio(0, "jpn", "Commentary", 2),
+            audio(1, "jpn", "", 6),
+            audio(2, "jpn", "", 2)
+        ]);
+
+        assert_eq!(kept_indices(&analyze_audio_tracks(&mkv, &filters(&["--keep-commentary"]))), vec![0, 2]);
+    }
+
+    #[test]
+    fn mono_survives_next_to_surround() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            audio(0, "jpn", "", 1),
+            audio(1, "jpn", "", 6)
+        ]);
+
+        assert_eq!(kept_indices(&analyze_audio_tracks(&mkv, &filters(&[]))), vec![0]);
+    }
+
+    #[test]
+    fn untagged_audio_is_kept_for_the_default_language() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            audio(0, "und", "", 2),
+            audio(1, "eng", "English", 2),
+            audio(2, "und", "", 2)
+        ]);
+
+        let kept = analyze_audio_tracks(&mkv, &filters(&["--default-audio-lang", "eng"]));
+
+        assert_eq!(kept_indices(&kept), vec![0, 2]);
+        assert_eq!(untagged_tracks(&kept), vec![0, 1]);
+    }
+
+    #[test]
+    fn external_sub_doesnt_hide_a_missing_track() {
+        let (jpn, eng, external) = (sub(0, "jpn"), sub(1, "eng"), sub(2, "spa"));
+        let missing = missing_tracks(&[(0, &jpn), (1, &eng)], &[&jpn, &external]);
+
+        assert_eq!(missing, vec![String::from("#1 (eng, ass)")]);
+    }
+
+    #[test]
+    fn font_mime_type_wins_over_an_odd_file_name() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            attachment(0, "Some Font.bin", "application/x-truetype-font"),
+            attachment(1, "cover.jpg", "image/jpeg")
+        ]);
+
+        assert_eq!(kept_indices(&analyze_attachments(&mkv, &filters(&[]), &[])), vec![0]);
+    }
+
+    #[test]
+    fn file_name_decides_without_a_mime_type() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            attachment(0, "font.ttf", ""),
+            attachment(1, "notes.txt", "application/octet-stream")
+        ]);
+
+        assert_eq!(kept_indices(&analyze_attachments(&mkv, &filters(&[]), &[])), vec![0]);
+    }
+}
@@ -1,6 +1,7 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, ErrorKind, Parser, ValueEnum};
+use regex::Regex;
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
 pub enum PreloadMode {
@@ -16,45 +17,948 @@ pub enum TranscodeMode {
     Never
 }
 
-#[derive(Parser, Debug)]
-#[clap(author, about)]
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum OutputBitDepth {
+    Source,
+    #[clap(name = "8")]
+    Eight,
+    #[clap(name = "10")]
+    Ten
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum EncoderProfile {
+    Anime,
+    Film,
+    Grain
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SvtLogLevel {
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug
+}
+
+impl SvtLogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SvtLogLevel::Fatal => "fatal",
+            SvtLogLevel::Error => "error",
+            SvtLogLevel::Warn => "warn",
+            SvtLogLevel::Info => "info",
+            SvtLogLevel::Debug => "debug",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum QualityMetric {
+    Ssim,
+    Vmaf
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SortOrder {
+    Name,
+    Size,
+    SizeDesc,
+    Duration
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum OverwritePolicy {
+    Always,
+    Never,
+    Newer
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum VfrMode {
+    Auto,
+    Cfr,
+    Passthrough
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum HwAccel {
+    Auto,
+    Cuda,
+    Vaapi,
+    Qsv
+}
+
+impl HwAccel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HwAccel::Auto => "auto",
+            HwAccel::Cuda => "cuda",
+            HwAccel::Vaapi => "vaapi",
+            HwAccel::Qsv => "qsv",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum Tonemap {
+    Off,
+    Hable,
+    Mobius,
+    Reinhard
+}
+
+impl Tonemap {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tonemap::Off => "off",
+            Tonemap::Hable => "hable",
+            Tonemap::Mobius => "mobius",
+            Tonemap::Reinhard => "reinhard",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum Downmix {
+    Default,
+    Dialogue,
+    Nightmode
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SubsFallback {
+    None,
+    KeepAll,
+    KeepFirst
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum TrackOrder {
+    Original,
+    Grouped,
+    PreferredLangFirst
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SubsPolicy {
+    Auto,
+    Minimal
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum AttachmentPolicy {
+    Fonts,
+    All,
+    None
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum HashAlgorithm {
+    Seahash,
+    Blake3,
+    Sha256,
+    None
+}
+
+impl HashAlgorithm {
+    /// The extension for sidecar hash files, the one the usual tools for each algorithm look for.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Seahash => "seahash",
+            HashAlgorithm::Blake3 => "b3",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::None => "",
+        }
+    }
+
+    /// The external tool that computes the hash, for the algorithms there's no crate around for.
+    pub fn tool(&self) -> Option<&'static str> {
+        match self {
+            HashAlgorithm::Blake3 => Some("b3sum"),
+            HashAlgorithm::Sha256 => Some("sha256sum"),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum OpusBitrate {
+    /// The usual bitrate for stereo, which is all transcoded audio ends up as.
+    Auto,
+    Fixed(String)
+}
+
+fn parse_opus_bitrate(value: &str) -> Result<OpusBitrate, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(OpusBitrate::Auto);
+    }
+
+    // Plain numbers are taken as kbps, ffmpeg would read them as bps.
+    let digits = value.strip_suffix(['k', 'K']).unwrap_or(value);
+
+    match digits.parse::<u32>() {
+        Ok(kbps) => Ok(OpusBitrate::Fixed(format!("{kbps}k"))),
+        Err(_) => Err(format!("'{value}' isn't 'auto' or a bitrate like '128k'"))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum BurnSubs {
+    /// The default track, or the first one that isn't just signs and songs.
+    Auto,
+    /// A subtitle stream index, same as on 0:s:N.
+    Track(usize)
+}
+
+fn parse_burn_subs(value: &str) -> Result<BurnSubs, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(BurnSubs::Auto);
+    }
+
+    match value.parse::<usize>() {
+        Ok(idx) => Ok(BurnSubs::Track(idx)),
+        Err(_) => Err(format!("'{value}' isn't 'auto' or a subtitle track index"))
+    }
+}
+
+fn parse_bitrate(value: &str) -> Result<String, String> {
+    let (digits, unit) = match value.strip_suffix(['k', 'K']) {
+        Some(digits) => (digits, "k"),
+        None => match value.strip_suffix(['m', 'M']) {
+            Some(digits) => (digits, "M"),
+            // Plain numbers are taken as kbps, ffmpeg would read them as bps.
+            None => (value, "k")
+        }
+    };
+
+    match digits.parse::<u32>() {
+        Ok(rate) => Ok(format!("{rate}{unit}")),
+        Err(_) => Err(format!("'{value}' isn't a bitrate like '8M' or '8000k'"))
+    }
+}
+
+/// An output frame rate, as ffmpeg takes it and as a number.
+#[derive(Clone, Debug)]
+pub struct FrameRate {
+    rate: String,
+    fps: f64
+}
+
+impl FrameRate {
+    pub fn as_str(&self) -> &str {
+        &self.rate
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+}
+
+fn parse_frame_rate(value: &str) -> Result<FrameRate, String> {
+    // The NTSC rates are never exactly what people type for them.
+    let rate = match value {
+        "23.976" | "23.98" => "24000/1001",
+        "29.97" => "30000/1001",
+        "59.94" => "60000/1001",
+        _ => value
+    };
+
+    let fps = match rate.split_once('/') {
+        Some((num, den)) => num.parse::<f64>().ok().zip(den.parse::<f64>().ok()).map(| (num, den) | num / den),
+        None => rate.parse::<f64>().ok()
+    };
+
+    match fps.filter(| fps | fps.is_finite() && *fps > 0.0) {
+        Some(fps) => Ok(FrameRate { rate: rate.to_owned(), fps }),
+        None => Err(format!("'{value}' isn't a frame rate like '25', '23.976' or '24000/1001'"))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Subs,
+    Attachments
+}
+
+/// A check on how many tracks of a kind (optionally in a language or codec) an output ends up with, like `audio[jpn]==1`.
+#[derive(Clone, Debug)]
+pub struct TrackAssertion {
+    expression: String,
+    kind: TrackKind,
+    filter: Option<String>,
+    operator: String,
+    count: usize
+}
+
+impl TrackAssertion {
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn kind(&self) -> TrackKind {
+        self.kind
+    }
+
+    /// The language or codec the counted tracks need to have, if any.
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+
+    pub fn holds(&self, count: usize) -> bool {
+        match self.operator.as_str() {
+            "==" => count == self.count,
+            "!=" => count != self.count,
+            ">=" => count >= self.count,
+            "<=" => count <= self.count,
+            ">" => count > self.count,
+            _ => count < self.count
+        }
+    }
+}
+
+fn parse_track_assertion(value: &str) -> Result<TrackAssertion, String> {
+    let invalid = || format!("'{value}' isn't an assertion like 'audio==1' or 'subs[ass]>=1'");
+
+    // Two character operators first, or '>=' would be read as '>'.
+    let (left, operator, right) = ["==", "!=", ">=", "<=", ">", "<"].iter()
+        .find_map(| op | value.split_once(op).map(| (left, right) | (left.trim(), *op, right.trim())))
+        .ok_or_else(invalid)?
+    ;
+
+    let (kind, filter) = match left.split_once('[') {
+        Some((kind, filter)) => (kind, Some(filter.strip_suffix(']').ok_or_else(invalid)?.to_lowercase())),
+        None => (left, None)
+    };
+
+    let kind = match kind {
+        "video" => TrackKind::Video,
+        "audio" => TrackKind::Audio,
+        "subs" => TrackKind::Subs,
+        "attachments" => TrackKind::Attachments,
+        _ => return Err(format!("'{kind}' isn't one of video, audio, subs or attachments"))
+    };
+
+    let count = right.parse::<usize>().map_err(| _ | invalid())?;
+
+    Ok(TrackAssertion { expression: value.trim().to_owned(), kind, filter, operator: operator.to_owned(), count })
+}
+
+fn parse_svtav1_params(value: &str) -> Result<String, String> {
+    for param in value.split(':') {
+        match param.split_once('=') {
+            Some((key, val)) if !key.is_empty() && !val.is_empty() => {}
+            _ => return Err(format!("'{param}' isn't a key=value pair"))
+        }
+    }
+
+    Ok(value.to_owned())
+}
+
+#[derive(Parser, Clone, Debug)]
+#[clap(
+    author,
+    about,
+    after_help = "Options marked with [env: ...] can also be set through that environment variable, flags take precedence."
+)]
 pub struct AppArgs {
     #[clap(
-        short = 'i',
+        short = 'i',
+        long,
+        env = "MKVCRUNCHER_INPUT_DIR",
+        required = true,
+        value_delimiter = ',',
+        help="The directories with MKV files to process. Can be passed multiple times, or separated by commas."
+    )]
+    input_dir: Vec<PathBuf>,
+    #[clap(
+        short = 'o',
+        long,
+        env = "MKVCRUNCHER_OUTPUT_DIR",
+        required_unless_present = "list",
+        help="The directory to save processed MKV files to. '-' writes the output to stdout instead, for a single input file."
+    )]
+    output_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        env = "MKVCRUNCHER_INTERMEDIATE_DIR",
+        help="A directory for ffmpeg to write the output files to, which are then moved by the cruncher to output_dir."
+    )]
+    intermediate_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        requires = "intermediate-dir",
+        help="Only route files with transcoded video through intermediate_dir, copied files are written to output_dir directly."
+    )]
+    intermediate_only_transcodes: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        env = "MKVCRUNCHER_PRELOAD_MODE",
+        default_value_t = PreloadMode::Auto,
+        help="Whether to force preload of mkv files into memory, read them from disk, or let mkv_cruncher decide."
+    )]
+    preload_mode: PreloadMode,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        env = "MKVCRUNCHER_TRANSCODE_MODE",
+        default_value_t = TranscodeMode::Auto,
+        help="Whether to force transcode of video streams, copy them, or let mkv_cruncher decide."
+    )]
+    transcode_mode: TranscodeMode,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        env = "MKVCRUNCHER_AUDIO_MODE",
+        default_value_t = TranscodeMode::Auto,
+        help="Whether to force transcode of audio streams to Opus, copy them, or only transcode lossless ones. Independent of --transcode-mode."
+    )]
+    audio_mode: TranscodeMode,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help="Extra attachment MIME types to keep besides fonts, separated by commas."
+    )]
+    keep_attachment_mimes: Vec<String>,
+    #[clap(
+        long,
+        help="Copy data streams and other stream types unknown to mkv_cruncher instead of dropping them."
+    )]
+    copy_unknown_streams: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        env = "MKVCRUNCHER_PROFILE",
+        help="A bundle of encoder settings tuned for a kind of content. Explicit encoder flags take precedence."
+    )]
+    profile: Option<EncoderProfile>,
+    #[clap(
+        long,
+        env = "MKVCRUNCHER_CRF",
+        value_parser = clap::value_parser!(u8).range(0..=63),
+        help="The CRF value used when transcoding video."
+    )]
+    crf: Option<u8>,
+    #[clap(
+        long,
+        env = "MKVCRUNCHER_PRESET",
+        value_parser = clap::value_parser!(u8).range(0..=13),
+        help="The SVT-AV1 preset used when transcoding video."
+    )]
+    preset: Option<u8>,
+    #[clap(
+        long,
+        env = "MKVCRUNCHER_FILM_GRAIN",
+        value_parser = clap::value_parser!(u8).range(0..=50),
+        help="The strength of SVT-AV1's film grain synthesis when transcoding video."
+    )]
+    film_grain: Option<u8>,
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(0..=120),
+        help="How many frames SVT-AV1 looks ahead when transcoding video. More can help quality at the cost of speed and memory."
+    )]
+    lookahead: Option<u8>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        help="SVT-AV1's log verbosity. Defaults to the SVT_LOG environment variable if set, fatal otherwise."
+    )]
+    svt_log_level: Option<SvtLogLevel>,
+    #[clap(
+        long,
+        help="Continue a previous batch, skipping files the manifest on output_dir lists as done."
+    )]
+    resume: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        env = "MKVCRUNCHER_OUTPUT_BIT_DEPTH",
+        default_value_t = OutputBitDepth::Ten,
+        help="The bit depth of transcoded video. 'source' keeps the source's bit depth where the encoder supports it."
+    )]
+    output_bit_depth: OutputBitDepth,
+    #[clap(
+        short = 'r',
+        long,
+        help="Look for MKV files on subdirectories of input_dir too, mirroring the structure on output_dir."
+    )]
+    recursive: bool,
+    #[clap(
+        long,
+        requires = "recursive",
+        help="Write every output directly into output_dir instead of mirroring input_dir's structure."
+    )]
+    flatten: bool,
+    #[clap(
+        long,
+        help="Print a summary of every matched file and exit without processing anything."
+    )]
+    list: bool,
+    #[clap(
+        long,
+        help="Skip files shorter than this many seconds, like samples or trailers."
+    )]
+    min_duration: Option<f64>,
+    #[clap(
+        long,
+        default_value = "jpn",
+        help="Subtitles in this language are always kept, regardless of the other subtitle filters."
+    )]
+    priority_sub_lang: String,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u8).range(0..=63),
+        help="Encode a sample of each file at these CRF values and report the results, without producing any output."
+    )]
+    crf_sweep: Vec<u8>,
+    #[clap(
+        long,
+        default_value_t = 60,
+        help="The length in seconds of the sample used by --crf-sweep."
+    )]
+    sample_length: u64,
+    #[clap(
+        long,
+        help="Replace the progress bar with plain log lines. Implied when stdout isn't a terminal."
+    )]
+    no_progress: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        help="Compare transcoded outputs against their source and log the score. Slow, vmaf needs an ffmpeg build with libvmaf."
+    )]
+    measure_quality: Option<QualityMetric>,
+    #[clap(
+        long,
+        help="Drop font attachments when no ASS/SSA subtitles are kept."
+    )]
+    smart_attachments: bool,
+    #[clap(
+        long,
+        help="The maximum number of audio tracks to keep after filtering."
+    )]
+    max_audio_tracks: Option<usize>,
+    #[clap(
+        long,
+        help="The maximum number of subtitle tracks to keep after filtering."
+    )]
+    max_sub_tracks: Option<usize>,
+    #[clap(
+        short = 'y',
+        long,
+        help="Don't ask for confirmation before runs that overwrite the source files."
+    )]
+    yes: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = SortOrder::Name,
+        help="The order files are processed in. Sorting by duration needs to probe every file first."
+    )]
+    sort: SortOrder,
+    #[clap(
+        long,
+        help="Copy the source file instead when the processed output ends up bigger than it."
+    )]
+    keep_smaller: bool,
+    #[clap(
+        long,
+        help="Keep commentary audio tracks instead of dropping them."
+    )]
+    keep_commentary: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = OverwritePolicy::Always,
+        help="What to do when the output file already exists. 'newer' only redoes it if the source was modified after it."
+    )]
+    overwrite: OverwritePolicy,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help="Container-level tags to carry over from the source despite the metadata cleanup, separated by commas."
+    )]
+    keep_metadata: Vec<String>,
+    #[clap(
+        long,
+        help="Only clean up metadata, keeping every stream as-is. Uses mkvpropedit if installed, a stream-copy remux otherwise."
+    )]
+    rename_only: bool,
+    #[clap(
+        long,
+        help="Keep the source's timestamps instead of shifting them to start at zero. Useful when outputs get concatenated later."
+    )]
+    copy_timestamps: bool,
+    #[clap(
+        long,
+        help="Write a per-file report of the batch (sizes, savings, kept tracks, timings) to this file."
+    )]
+    report: Option<PathBuf>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        requires = "report",
+        default_value_t = ReportFormat::Json,
+        help="The format of the file written by --report."
+    )]
+    report_format: ReportFormat,
+    #[clap(
+        long,
+        help="How many bytes ffprobe and ffmpeg read to detect streams. Raise it for files with late-starting streams."
+    )]
+    probe_size: Option<u64>,
+    #[clap(
+        long,
+        help="How many seconds of the file ffprobe and ffmpeg analyze to detect streams and durations."
+    )]
+    analyze_duration: Option<u64>,
+    #[clap(
+        long,
+        help="Copy lossless audio (FLAC, TrueHD, DTS, PCM) as-is instead of transcoding it to Opus, regardless of --audio-mode."
+    )]
+    keep_lossless: bool,
+    #[clap(
+        long,
+        value_parser = parse_opus_bitrate,
+        help="The bitrate of audio transcoded to Opus, like '128k'. 'auto' is 128k, transcoded audio is always downmixed to stereo."
+    )]
+    opus_bitrate: Option<OpusBitrate>,
+    #[clap(
+        long,
+        env = "MKVCRUNCHER_TEMP_DIR",
+        help="A directory for ffmpeg's own scratch files and CRF sweep samples, instead of the working directory."
+    )]
+    temp_dir: Option<PathBuf>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = VfrMode::Auto,
+        help="How transcoded video handles frame timing. 'auto' passes timestamps through only for sources that look VFR."
+    )]
+    vfr: VfrMode,
+    #[clap(
+        long,
+        help="Tag untagged audio and subtitle tracks as the language the file name mentions, like [JPN] or .eng."
+    )]
+    infer_lang_from_filename: bool,
+    #[clap(
+        long,
+        help="A directory with external subtitle files to mux in. Files named after the MKV (Show - 01.eng.ass) get added as extra tracks."
+    )]
+    external_subs: Option<PathBuf>,
+    #[clap(
+        long,
+        help="A directory with font files to attach to every output."
+    )]
+    external_fonts: Option<PathBuf>,
+    #[clap(
+        long,
+        help="Fail a file on any warning about it (truncated bit depth, VFR, missing tracks, duration mismatch) instead of carrying on."
+    )]
+    strict: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        help="Decode video on the GPU when transcoding. Falls back to software decode if the device can't be set up."
+    )]
+    hwaccel: Option<HwAccel>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help="Audio codecs to prefer when several tracks in the same language survive filtering, best first. Like flac,dts,ac3."
+    )]
+    prefer_audio_codec: Vec<String>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "1,2",
+        help="Channel counts preferred when several audio tracks survive filtering, others (like 5.1) get dropped if any of these are left."
+    )]
+    keep_channels: Vec<u64>,
+    #[clap(
+        long,
+        value_parser = Regex::new,
+        help="Only process files whose name matches this regex, like 'S0[12]E\\d+'."
+    )]
+    name_filter: Option<Regex>,
+    #[clap(
+        long,
+        help="Analyze every file and estimate the output sizes without processing anything. The estimates are rough."
+    )]
+    dry_run: bool,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = Tonemap::Off,
+        help="Tone-map HDR sources down to SDR when transcoding, with this curve. 'off' keeps HDR and its colour tags."
+    )]
+    tonemap: Tonemap,
+    #[clap(
+        long,
+        help="Process at most this many files, after sorting and filtering. Pairs well with --resume for batches in chunks."
+    )]
+    limit: Option<usize>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = Downmix::Default,
+        help="How surround audio gets downmixed to stereo. 'dialogue' boosts the center channel, 'nightmode' also evens out the volume."
+    )]
+    downmix: Downmix,
+    #[clap(
+        long,
+        require_equals = true,
+        value_name = "LUFS",
+        help="Normalize the loudness of transcoded audio, optionally to a target like --loudnorm=-16. Defaults to EBU R128's -23 LUFS."
+    )]
+    loudnorm: Option<Option<f64>>,
+    #[clap(
+        long,
+        requires = "loudnorm",
+        help="Measure each track before normalizing it. More accurate than the single pass --loudnorm, but slower."
+    )]
+    loudnorm_2pass: bool,
+    #[clap(
+        long,
+        help="Treat the inputs as a network mount: never preload them and skip the full-file hash check. Detected automatically for NFS and SMB mounts."
+    )]
+    remote_input: bool,
+    #[clap(
+        long,
+        help="Give outputs the same permissions as their source file, instead of the defaults for new files."
+    )]
+    preserve_permissions: bool,
+    #[clap(
+        long,
+        help="Keep running after the batch, crunching new files as they show up on the input directories. Files are picked up once their size stops changing."
+    )]
+    watch: bool,
+    #[clap(
+        long,
+        value_parser = parse_svtav1_params,
+        help="Extra SVT-AV1 parameters, as key=value pairs separated by colons (\"enable-overlays=1:scd=1\"). Take precedence over the ones set by other options, like --film-grain and --lookahead."
+    )]
+    svtav1_params: Option<String>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = AttachmentPolicy::Fonts,
+        help="Which attachments to keep. 'fonts' keeps fonts (and --keep-attachment-mimes), 'all' keeps everything, 'none' drops them all."
+    )]
+    attachments: AttachmentPolicy,
+    #[clap(
         long,
-        help="The directory with MKV files to process."
+        value_parser = parse_burn_subs,
+        help="Burn a subtitle track into the video, by its index among the subtitle tracks or 'auto' for the default dialogue track. Forces video transcoding and drops the track from the output. Text subs only."
     )]
-    input_dir: PathBuf,
+    burn_subs: Option<BurnSubs>,
     #[clap(
-        short = 'o',
         long,
-        help="The directory to save processed MKV files to."
+        value_parser = clap::value_parser!(i32).range(0..=19),
+        help="Run ffmpeg with this niceness (0-19), so encodes yield to interactive work. Also lowers its IO priority on Linux, 19 only gets idle IO time."
     )]
-    output_dir: PathBuf,
+    nice: Option<i32>,
     #[clap(
         long,
-        help="A directory for ffmpeg to write the output files to, which are then moved by the cruncher to output_dir."
+        help="Throw away transcodes that don't shrink the file by at least this many percent, keeping the source instead."
     )]
-    intermediate_dir: Option<PathBuf>,
+    min_savings: Option<f64>,
+    #[clap(
+        long,
+        help="Tag kept audio tracks without a language (\"und\") as this one, like jpn. --infer-lang-from-filename goes first."
+    )]
+    default_audio_lang: Option<String>,
+    #[clap(
+        long,
+        help="Tag kept subtitle tracks without a language (\"und\") as this one, like eng. --infer-lang-from-filename goes first."
+    )]
+    default_sub_lang: Option<String>,
+    #[clap(
+        long,
+        help="Mux in Matroska audio (.mka) and subtitle (.mks) files named after the MKV, from the same directory. Their tracks go through the same filters as the MKV's own."
+    )]
+    sidecars: bool,
     #[clap(
         arg_enum,
         value_parser,
         long,
-        default_value_t = PreloadMode::Auto,
-        help="Whether to force preload of mkv files into memory, read them from disk, or let mkv_cruncher decide."
+        default_value_t = SubsFallback::None,
+        help="What to do when the filters would drop every subtitle track. 'none' lets them go, 'keep-all' keeps them all, 'keep-first' keeps the first one."
     )]
-    preload_mode: PreloadMode,
+    subs_fallback: SubsFallback,
+    #[clap(
+        long,
+        value_parser = parse_bitrate,
+        help="Cap the video bitrate while still encoding by CRF, like 8M. Plain numbers are taken as kbps."
+    )]
+    maxrate: Option<String>,
+    #[clap(
+        long,
+        requires = "maxrate",
+        value_parser = parse_bitrate,
+        help="The buffer --maxrate is checked over, like 16M. Smaller buffers follow the cap more strictly."
+    )]
+    bufsize: Option<String>,
+    #[clap(
+        long,
+        help="Set the title to the file name instead of clearing it, without the extension and [bracketed] tags like release groups. Takes precedence over keeping the title through --keep-metadata."
+    )]
+    set_title_from_filename: bool,
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..),
+        help="Probe every file up front, this many at a time, instead of one by one as the batch goes. Speeds up big batches, especially over the network."
+    )]
+    max_concurrent_probes: Option<u8>,
+    #[clap(
+        long,
+        help="Decode each file in full before processing it, and skip the ones that report errors. They show up as failed in the summary, with their error count in the report."
+    )]
+    precheck: bool,
+    #[clap(
+        long,
+        help="Make encodes reproducible, so the same input and settings always give a byte-identical output. Runs the encoder single-threaded, which is a lot slower, and leaves out the muxing date and random track IDs."
+    )]
+    deterministic: bool,
+    #[clap(
+        long,
+        help="Also copy the untouched source next to each crunched output, as \"<name>.original.<ext>\", to compare the two before deleting anything. Unlike --keep-smaller, both files are kept."
+    )]
+    keep_original: bool,
     #[clap(
         arg_enum,
         value_parser,
         long,
-        default_value_t = TranscodeMode::Auto,
-        help="Whether to force transcode of video streams, copy them, or let mkv_cruncher decide."
+        default_value_t = HashAlgorithm::Seahash,
+        help="The hash used to verify outputs copied from the intermediate dir, and recorded on the report. blake3 and sha256 need b3sum and sha256sum on the PATH. 'none' checks copies by size only."
+    )]
+    hash: HashAlgorithm,
+    #[clap(
+        long,
+        help="Write each output's hash next to it, as \"<output>.<algorithm>\", in the format sha256sum -c and b3sum -c can check. Needs a --hash other than 'none'."
+    )]
+    hash_sidecar: bool,
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(2..),
+        help="Experimental. Encode the video in this many pieces and stitch them back together, so a crash only loses the piece it happened on. Running again over the same file picks up the finished pieces. Doesn't apply when burning in subtitles."
+    )]
+    segmented: Option<u8>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help="More audio languages to keep on top of Japanese, Chinese and untagged ones, like kor,eng. Commentary, channel and codec preferences still apply to them."
+    )]
+    extra_audio_langs: Vec<String>,
+    #[clap(
+        long,
+        value_parser = parse_frame_rate,
+        help="Convert the video to this frame rate, like 23.976 or 24000/1001. Frames get dropped or duplicated to keep the runtime (and audio sync) as-is. Forces a transcode on sources with a different rate."
+    )]
+    fps: Option<FrameRate>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = SubsPolicy::Auto,
+        help="How subtitle tracks get picked. 'auto' goes by titles, languages and formats, 'minimal' keeps the default track and any forced ones, and nothing else. --max-sub-tracks and --subs-fallback apply to both."
+    )]
+    subs: SubsPolicy,
+    #[clap(
+        long,
+        help="Stop at the first file that fails and exit with an error, skipping the rest of the batch. By default, failed files are noted on the summary and the batch keeps going."
+    )]
+    fail_fast: bool,
+    #[clap(
+        long,
+        help="Set extra metadata from a JSON file per input, like \"{stem}.json\" or \"meta/{name}.json\" ({stem} and {name} being the input's file name without and with the extension). Relative to each input's directory. Every key in the JSON object becomes a tag, after the usual cleanup."
+    )]
+    metadata_from: Option<String>,
+    #[clap(
+        long,
+        help="Wait this many seconds between files, to let the machine cool down on long batches."
+    )]
+    cooldown: Option<u64>,
+    #[clap(
+        long,
+        help="Pause before the next file while the CPU is hotter than this many °C, until it cools down a bit. Linux only, goes by /sys/class/thermal."
+    )]
+    max_temp: Option<f64>,
+    #[clap(
+        arg_enum,
+        value_parser,
+        long,
+        default_value_t = TrackOrder::Original,
+        help="The order tracks go in on the output. 'original' keeps the source's, 'grouped' goes video, audio, subs, 'preferred-lang-first' also puts Japanese audio and --priority-sub-lang subs first within their group. Tracks from other files always go after the source's own."
     )]
-    transcode_mode: TranscodeMode
+    reorder_tracks: TrackOrder,
+    #[clap(
+        long = "assert",
+        value_delimiter = ',',
+        value_parser = parse_track_assertion,
+        help="Fail files whose output wouldn't match these, like audio[jpn]==1,subs[ass]>=1. Counts video, audio, subs or attachments, optionally only those in a language or codec, against ==, !=, >=, <=, > or <. Any failure makes the run exit with an error."
+    )]
+    assertions: Vec<TrackAssertion>
 }
 
 impl AppArgs {
+    /// The conflicts clap can't express on its own, since they hinge on the value of `-o` and `--hash`.
+    /// Reported the same way as clap's own errors.
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        if self.output_to_stdout() {
+            let conflicts = [
+                ("--rename-only", self.rename_only),
+                ("--watch", self.watch),
+                ("--keep-original", self.keep_original),
+                ("--segmented", self.segmented.is_some()),
+                ("--crf-sweep", !self.crf_sweep.is_empty())
+            ];
+
+            if let Some((flag, _)) = conflicts.iter().find(| (_, set) | *set) {
+                return Err(AppArgs::command().error(
+                    ErrorKind::ArgumentConflict,
+                    format!("'--output-dir -' (writing to stdout) cannot be used with '{flag}'")
+                ));
+            }
+        }
+
+        if self.hash_sidecar && self.hash == HashAlgorithm::None {
+            return Err(AppArgs::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "'--hash-sidecar' requires a '--hash' other than 'none'"
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn preload_mode(&self) -> PreloadMode {
         self.preload_mode
     }
@@ -63,15 +967,362 @@ impl AppArgs {
         self.transcode_mode
     }
 
-    pub fn input_dir(&self) -> PathBuf {
+    pub fn audio_mode(&self) -> TranscodeMode {
+        self.audio_mode
+    }
+
+    pub fn input_dirs(&self) -> Vec<PathBuf> {
         self.input_dir.clone()
     }
 
     pub fn output_dir(&self) -> PathBuf {
-        self.output_dir.clone()
+        // Only missing with --list, which never writes anything.
+        self.output_dir.clone().unwrap_or_default()
+    }
+
+    pub fn output_to_stdout(&self) -> bool {
+        self.output_dir.as_deref() == Some(Path::new("-"))
     }
 
     pub fn intermediate_dir(&self) -> Option<PathBuf> {
         self.intermediate_dir.clone()
     }
+
+    pub fn intermediate_only_transcodes(&self) -> bool {
+        self.intermediate_only_transcodes
+    }
+
+    pub fn keep_attachment_mimes(&self) -> Vec<String> {
+        self.keep_attachment_mimes.clone()
+    }
+
+    pub fn copy_unknown_streams(&self) -> bool {
+        self.copy_unknown_streams
+    }
+
+    pub fn profile(&self) -> Option<EncoderProfile> {
+        self.profile
+    }
+
+    pub fn crf(&self) -> Option<u8> {
+        self.crf
+    }
+
+    pub fn preset(&self) -> Option<u8> {
+        self.preset
+    }
+
+    pub fn film_grain(&self) -> Option<u8> {
+        self.film_grain
+    }
+
+    pub fn lookahead(&self) -> Option<u8> {
+        self.lookahead
+    }
+
+    pub fn svt_log_level(&self) -> Option<SvtLogLevel> {
+        self.svt_log_level
+    }
+
+    pub fn resume(&self) -> bool {
+        self.resume
+    }
+
+    pub fn output_bit_depth(&self) -> OutputBitDepth {
+        self.output_bit_depth
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.recursive
+    }
+
+    pub fn flatten(&self) -> bool {
+        self.flatten
+    }
+
+    pub fn list(&self) -> bool {
+        self.list
+    }
+
+    pub fn min_duration(&self) -> Option<f64> {
+        self.min_duration
+    }
+
+    pub fn priority_sub_lang(&self) -> String {
+        self.priority_sub_lang.clone()
+    }
+
+    pub fn crf_sweep(&self) -> Vec<u8> {
+        self.crf_sweep.clone()
+    }
+
+    pub fn sample_length(&self) -> u64 {
+        self.sample_length
+    }
+
+    pub fn no_progress(&self) -> bool {
+        self.no_progress
+    }
+
+    pub fn measure_quality(&self) -> Option<QualityMetric> {
+        self.measure_quality
+    }
+
+    pub fn smart_attachments(&self) -> bool {
+        self.smart_attachments
+    }
+
+    pub fn max_audio_tracks(&self) -> Option<usize> {
+        self.max_audio_tracks
+    }
+
+    pub fn max_sub_tracks(&self) -> Option<usize> {
+        self.max_sub_tracks
+    }
+
+    pub fn yes(&self) -> bool {
+        self.yes
+    }
+
+    pub fn sort(&self) -> SortOrder {
+        self.sort
+    }
+
+    pub fn keep_smaller(&self) -> bool {
+        self.keep_smaller
+    }
+
+    pub fn keep_commentary(&self) -> bool {
+        self.keep_commentary
+    }
+
+    pub fn overwrite(&self) -> OverwritePolicy {
+        self.overwrite
+    }
+
+    pub fn keep_metadata(&self) -> Vec<String> {
+        self.keep_metadata.clone()
+    }
+
+    pub fn rename_only(&self) -> bool {
+        self.rename_only
+    }
+
+    pub fn copy_timestamps(&self) -> bool {
+        self.copy_timestamps
+    }
+
+    pub fn report(&self) -> Option<PathBuf> {
+        self.report.clone()
+    }
+
+    pub fn report_format(&self) -> ReportFormat {
+        self.report_format
+    }
+
+    pub fn probe_size(&self) -> Option<u64> {
+        self.probe_size
+    }
+
+    pub fn analyze_duration(&self) -> Option<u64> {
+        self.analyze_duration
+    }
+
+    pub fn keep_lossless(&self) -> bool {
+        self.keep_lossless
+    }
+
+    pub fn opus_bitrate(&self) -> Option<OpusBitrate> {
+        self.opus_bitrate.clone()
+    }
+
+    pub fn temp_dir(&self) -> Option<PathBuf> {
+        self.temp_dir.clone()
+    }
+
+    pub fn vfr(&self) -> VfrMode {
+        self.vfr
+    }
+
+    pub fn infer_lang_from_filename(&self) -> bool {
+        self.infer_lang_from_filename
+    }
+
+    pub fn external_subs(&self) -> Option<PathBuf> {
+        self.external_subs.clone()
+    }
+
+    pub fn external_fonts(&self) -> Option<PathBuf> {
+        self.external_fonts.clone()
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn hwaccel(&self) -> Option<HwAccel> {
+        self.hwaccel
+    }
+
+    pub fn prefer_audio_codecs(&self) -> Vec<String> {
+        self.prefer_audio_codec.clone()
+    }
+
+    pub fn keep_channels(&self) -> Vec<u64> {
+        self.keep_channels.clone()
+    }
+
+    pub fn name_filter(&self) -> Option<Regex> {
+        self.name_filter.clone()
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn tonemap(&self) -> Tonemap {
+        self.tonemap
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn downmix(&self) -> Downmix {
+        self.downmix
+    }
+
+    pub fn loudnorm(&self) -> Option<f64> {
+        self.loudnorm.map(| target | target.unwrap_or(DEFAULT_LOUDNORM_TARGET))
+    }
+
+    pub fn loudnorm_2pass(&self) -> bool {
+        self.loudnorm_2pass
+    }
+
+    pub fn remote_input(&self) -> bool {
+        self.remote_input
+    }
+
+    pub fn preserve_permissions(&self) -> bool {
+        self.preserve_permissions
+    }
+
+    pub fn watch(&self) -> bool {
+        self.watch
+    }
+
+    pub fn svtav1_params(&self) -> Option<String> {
+        self.svtav1_params.clone()
+    }
+
+    pub fn attachments(&self) -> AttachmentPolicy {
+        self.attachments
+    }
+
+    pub fn burn_subs(&self) -> Option<BurnSubs> {
+        self.burn_subs
+    }
+
+    pub fn nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    pub fn min_savings(&self) -> Option<f64> {
+        self.min_savings
+    }
+
+    pub fn default_audio_lang(&self) -> Option<String> {
+        self.default_audio_lang.clone()
+    }
+
+    pub fn default_sub_lang(&self) -> Option<String> {
+        self.default_sub_lang.clone()
+    }
+
+    pub fn sidecars(&self) -> bool {
+        self.sidecars
+    }
+
+    pub fn subs_fallback(&self) -> SubsFallback {
+        self.subs_fallback
+    }
+
+    pub fn maxrate(&self) -> Option<String> {
+        self.maxrate.clone()
+    }
+
+    pub fn bufsize(&self) -> Option<String> {
+        self.bufsize.clone()
+    }
+
+    pub fn set_title_from_filename(&self) -> bool {
+        self.set_title_from_filename
+    }
+
+    pub fn max_concurrent_probes(&self) -> Option<u8> {
+        self.max_concurrent_probes
+    }
+
+    pub fn precheck(&self) -> bool {
+        self.precheck
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn keep_original(&self) -> bool {
+        self.keep_original
+    }
+
+    pub fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    pub fn hash_sidecar(&self) -> bool {
+        self.hash_sidecar
+    }
+
+    pub fn segmented(&self) -> Option<u8> {
+        self.segmented
+    }
+
+    pub fn extra_audio_langs(&self) -> Vec<String> {
+        self.extra_audio_langs.iter().map(| lang | lang.to_lowercase()).collect()
+    }
+
+    pub fn fps(&self) -> Option<FrameRate> {
+        self.fps.clone()
+    }
+
+    pub fn subs(&self) -> SubsPolicy {
+        self.subs
+    }
+
+    pub fn fail_fast(&self) -> bool {
+        self.fail_fast
+    }
+
+    pub fn metadata_from(&self) -> Option<String> {
+        self.metadata_from.clone()
+    }
+
+    pub fn cooldown(&self) -> Option<u64> {
+        self.cooldown
+    }
+
+    pub fn max_temp(&self) -> Option<f64> {
+        self.max_temp
+    }
+
+    pub fn reorder_tracks(&self) -> TrackOrder {
+        self.reorder_tracks
+    }
+
+    pub fn assertions(&self) -> Vec<TrackAssertion> {
+        self.assertions.clone()
+    }
 }
+
+const DEFAULT_LOUDNORM_TARGET: f64 = -23.0;
@@ -0,0 +1,182 @@
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use seahash::SeaHasher;
+
+use crate::args::HashAlgorithm;
+
+/// Free space on the filesystem holding `path`, as far as an unprivileged user is concerned.
+/// None when it can't be told, which callers should take as "probably fine".
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether `a` and `b` live on the same filesystem, so writing to both eats from the same free space.
+#[cfg(unix)]
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// Whether `path` sits on a network filesystem (NFS, SMB, sshfs, etc), going by the mount table.
+#[cfg(target_os = "linux")]
+pub fn is_network_path(path: &Path) -> bool {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return false
+    };
+
+    let mounts = match fs::read_to_string("/proc/self/mounts") {
+        Ok(mounts) => mounts,
+        Err(_) => return false
+    };
+
+    // The longest mount point containing the path is the one it lives on.
+    let fs_type = mounts.lines()
+        .filter_map(| line | {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?;
+            let fs_type = fields.next()?;
+
+            Some((mount_point.replace("\\040", " "), fs_type))
+        })
+        .filter(| (mount_point, _) | path.starts_with(mount_point))
+        .max_by_key(| (mount_point, _) | mount_point.len())
+        .map(| (_, fs_type) | fs_type)
+    ;
+
+    fs_type.map(| fs_type | NETWORK_FILESYSTEMS.contains(&fs_type)).unwrap_or(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_network_path(_path: &Path) -> bool {
+    false
+}
+
+/// Whether `path` is a download that hasn't finished, going by its own name or a sibling marker file
+/// (`Show - 01.mkv.part`, qBittorrent's `.!qB`, etc).
+pub fn is_partial_download(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return false
+    };
+
+    PARTIAL_DOWNLOAD_SUFFIXES.iter().any(| suffix | {
+        name.ends_with(suffix) || path.with_file_name(format!("{name}{suffix}")).exists()
+    })
+}
+
+/// Hashes a file with `algorithm`, as a lowercase hex string. None with HashAlgorithm::None.
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<Option<String>> {
+    match algorithm {
+        HashAlgorithm::Seahash => seahash_file(path).map(| hash | Some(format!("{hash:016x}"))),
+        HashAlgorithm::Blake3 | HashAlgorithm::Sha256 => hash_with_tool(algorithm.tool().unwrap_or_default(), path).map(Some),
+        HashAlgorithm::None => Ok(None)
+    }
+}
+
+/// Whether `tool` can be run at all, going by `tool --version`.
+pub fn has_tool(tool: &str) -> bool {
+    Command::new(tool)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(| status | status.success())
+        .unwrap_or(false)
+}
+
+/// Writes `hash` next to `path`, named and formatted so the algorithm's usual tool can check it with -c.
+pub fn write_hash_sidecar(path: &Path, algorithm: HashAlgorithm, hash: &str) -> io::Result<PathBuf> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let sidecar_path = path.with_file_name(format!("{file_name}.{}", algorithm.extension()));
+
+    fs::write(&sidecar_path, format!("{hash}  {file_name}\n"))?;
+    Ok(sidecar_path)
+}
+
+// Hashes a file in chunks, instead of loading the whole thing into memory first.
+fn seahash_file(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(HASH_CHUNK_SIZE, File::open(path)?);
+    let mut hasher = SeaHasher::new();
+
+    loop {
+        let chunk = reader.fill_buf()?;
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        hasher.write(chunk);
+
+        let length = chunk.len();
+        reader.consume(length);
+    }
+
+    Ok(hasher.finish())
+}
+
+// sha256sum and b3sum both print the hash first, then the file name.
+fn hash_with_tool(tool: &str, path: &Path) -> io::Result<String> {
+    let result = Command::new(tool).arg(path).stderr(Stdio::null()).output()?;
+
+    if !result.status.success() {
+        return Err(io::Error::other(format!("{tool} exited with {}", result.status)));
+    }
+
+    // The line gets a leading backslash when the file name needs escaping.
+    String::from_utf8_lossy(&result.stdout)
+        .split_whitespace()
+        .next()
+        .map(| hash | hash.trim_start_matches('\\').to_owned())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{tool} didn't print a hash")))
+}
+
+#[cfg(target_os = "linux")]
+const NETWORK_FILESYSTEMS: [&str; 7] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "fuse.sshfs",
+    "fuse.rclone"
+];
+
+const PARTIAL_DOWNLOAD_SUFFIXES: [&str; 4] = [
+    ".part",
+    ".!qB",
+    ".crdownload",
+    ".tmp"
+];
+
+const HASH_CHUNK_SIZE: usize = 8 * 1024 * 1024;
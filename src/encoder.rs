@@ -0,0 +1,246 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::args::{AppArgs, Downmix, EncoderProfile, HwAccel, OpusBitrate, Tonemap};
+
+#[derive(Clone)]
+pub struct EncoderSettings {
+    crf: u8,
+    preset: u8,
+
+    tune: Option<u8>,
+    film_grain: Option<u8>,
+    lookahead: Option<u8>,
+    extra_params: Option<String>,
+
+    maxrate: Option<String>,
+    bufsize: Option<String>,
+
+    deterministic: bool
+}
+
+impl EncoderSettings {
+    pub fn new(cfg: &AppArgs) -> EncoderSettings {
+        let mut settings = match cfg.profile() {
+            Some(profile) => EncoderSettings::from_profile(profile),
+            None => EncoderSettings::default()
+        };
+
+        // Explicit flags always win over whatever the profile says.
+        if let Some(crf) = cfg.crf() {
+            settings.crf = crf;
+        }
+
+        if let Some(preset) = cfg.preset() {
+            settings.preset = preset;
+        }
+
+        if let Some(film_grain) = cfg.film_grain() {
+            settings.film_grain = Some(film_grain);
+        }
+
+        settings.lookahead = cfg.lookahead();
+        settings.extra_params = cfg.svtav1_params();
+
+        settings.maxrate = cfg.maxrate();
+        settings.bufsize = cfg.bufsize();
+
+        settings.deterministic = cfg.deterministic();
+
+        settings
+    }
+
+    fn from_profile(profile: EncoderProfile) -> EncoderSettings {
+        match profile {
+            // Flat colours and clean lines compress really well, grain synthesis only adds noise here.
+            EncoderProfile::Anime => EncoderSettings { crf: 32, preset: 6, tune: Some(0), film_grain: None, ..EncoderSettings::default() },
+            EncoderProfile::Film => EncoderSettings { crf: 28, preset: 6, tune: Some(0), film_grain: Some(8), ..EncoderSettings::default() },
+            EncoderProfile::Grain => EncoderSettings { crf: 26, preset: 6, tune: Some(0), film_grain: Some(20), ..EncoderSettings::default() },
+        }
+    }
+
+    pub fn set_crf(&mut self, crf: u8) {
+        self.crf = crf;
+    }
+
+    pub fn ffmpeg_arguments(&self) -> Vec<String> {
+        let mut arguments = vec![
+            String::from("-c:v"), String::from("libsvtav1"),
+            String::from("-crf"), self.crf.to_string(),
+            String::from("-preset"), self.preset.to_string(),
+        ];
+
+        // Capped CRF, quality still comes from the CRF but complex scenes can't go over the cap.
+        if let Some(maxrate) = self.maxrate.as_ref() {
+            arguments.push(String::from("-maxrate"));
+            arguments.push(maxrate.clone());
+        }
+
+        if let Some(bufsize) = self.bufsize.as_ref() {
+            arguments.push(String::from("-bufsize"));
+            arguments.push(bufsize.clone());
+        }
+
+        let mut svt_params = Vec::new();
+
+        if let Some(tune) = self.tune {
+            svt_params.push((String::from("tune"), tune.to_string()));
+        }
+
+        if let Some(film_grain) = self.film_grain {
+            svt_params.push((String::from("film-grain"), film_grain.to_string()));
+        }
+
+        if let Some(lookahead) = self.lookahead {
+            svt_params.push((String::from("lookahead"), lookahead.to_string()));
+        }
+
+        // SVT-AV1 is only bit-exact between runs with a single thread.
+        if self.deterministic {
+            svt_params.push((String::from("lp"), String::from("1")));
+        }
+
+        // Whoever passes raw parameters knows what they want, those replace anything set above.
+        if let Some(extra_params) = self.extra_params.as_ref() {
+            for (key, value) in extra_params.split(':').filter_map(| param | param.split_once('=')) {
+                svt_params.retain(| (k, _) | k != key);
+                svt_params.push((key.to_owned(), value.to_owned()));
+            }
+        }
+
+        if !svt_params.is_empty() {
+            let svt_params: Vec<String> = svt_params.iter().map(| (key, value) | format!("{key}={value}")).collect();
+
+            arguments.push(String::from("-svtav1-params"));
+            arguments.push(svt_params.join(":"));
+        }
+
+        arguments
+    }
+
+    /// A rough guess of the output size for a `source_size` byte file with `source_codec` video.
+    /// Goes by how much AV1 usually saves over the source codec at CRF 30, scaled for the actual CRF.
+    pub fn estimate_output_size(&self, source_size: u64, source_codec: &str) -> u64 {
+        let codec_ratio = match source_codec {
+            "mpeg2video" | "mpeg4" | "msmpeg4v3" => 0.3,
+            "h264" => 0.5,
+            "hevc" | "vp9" => 0.75,
+            _ => 0.6
+        };
+
+        // Around every 8 CRF steps halve (or double) the bitrate.
+        let crf_ratio = 2f64.powf((ESTIMATE_BASE_CRF - self.crf as f64) / 8.0);
+
+        (source_size as f64 * (codec_ratio * crf_ratio).min(1.0)) as u64
+    }
+}
+
+impl Default for EncoderSettings {
+    fn default() -> EncoderSettings {
+        EncoderSettings {
+            crf: 30,
+            preset: 7,
+
+            tune: None,
+            film_grain: None,
+            lookahead: None,
+            extra_params: None,
+
+            maxrate: None,
+            bufsize: None,
+
+            deterministic: false
+        }
+    }
+}
+
+pub fn pixel_format(bit_depth: u8) -> &'static str {
+    if bit_depth == 8 {
+        "yuv420p"
+    }
+    else {
+        "yuv420p10le"
+    }
+}
+
+/// A filter chain that tone-maps HDR video down to BT.709 SDR, ending on `pix_fmt`. Needs an ffmpeg build with zimg.
+pub fn tonemap_filter(tonemap: Tonemap, pix_fmt: &str) -> String {
+    format!(
+        "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap=tonemap={}:desat=0,zscale=t=bt709:m=bt709:r=tv,format={pix_fmt}",
+        tonemap.as_str()
+    )
+}
+
+/// A filter that renders subtitle stream `sub_idx` of `file` onto the video. Needs an ffmpeg build with libass.
+pub fn subtitles_filter(file: &Path, sub_idx: usize) -> String {
+    // The path goes through two rounds of unescaping, once as an option value and once as part of the filtergraph.
+    let path = file.to_string_lossy();
+    let path = escape(&path, &['\\', '\'', ':']);
+    let path = escape(&path, &['\\', '\'', '[', ']', ',', ';']);
+
+    format!("subtitles=filename={path}:si={sub_idx}")
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// A filter that downmixes a `channels` channel track to stereo, keeping the center (dialogue) channel up front.
+/// None for layouts it doesn't know, or with the default downmix, where -ac 2 is left to do the job.
+pub fn downmix_filter(downmix: Downmix, channels: u64) -> Option<String> {
+    // Channels go by position, 5.1 and 5.1(side) name their surrounds differently.
+    // FL FR FC LFE BL BR (SL SR)
+    let pan = match channels {
+        6 => "pan=stereo|c0=c2+0.30*c0+0.30*c4|c1=c2+0.30*c1+0.30*c5",
+        8 => "pan=stereo|c0=c2+0.30*c0+0.30*c4+0.30*c6|c1=c2+0.30*c1+0.30*c5+0.30*c7",
+        _ => return None
+    };
+
+    match downmix {
+        Downmix::Default => None,
+        Downmix::Dialogue => Some(String::from(pan)),
+        Downmix::Nightmode => Some(format!("{pan},dynaudnorm"))
+    }
+}
+
+/// The Opus bitrate for a transcoded track. Those always get downmixed to stereo, so that's what auto goes by.
+pub fn opus_bitrate(setting: &OpusBitrate) -> String {
+    match setting {
+        OpusBitrate::Fixed(bitrate) => bitrate.clone(),
+        OpusBitrate::Auto => String::from(STEREO_OPUS_BITRATE)
+    }
+}
+
+/// Whether ffmpeg can set up a `hwaccel` device on this machine.
+/// A specific device that fails to initialize makes ffmpeg bail instead of falling back to software decode.
+pub fn hwaccel_available(hwaccel: HwAccel) -> bool {
+    // ffmpeg already falls back on its own with auto.
+    if let HwAccel::Auto = hwaccel {
+        return true;
+    }
+
+    Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-init_hw_device", hwaccel.as_str()])
+        .args(["-f", "lavfi", "-i", "nullsrc", "-frames:v", "1", "-f", "null", "-"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(| status | status.success())
+        .unwrap_or(false)
+}
+
+// The CRF the codec ratios in estimate_output_size were eyeballed at.
+const ESTIMATE_BASE_CRF: f64 = 30.0;
+
+// Transparent for stereo Opus.
+const STEREO_OPUS_BITRATE: &str = "128k";
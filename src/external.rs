@@ -0,0 +1,189 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::analyze;
+
+/// A subtitle file shipped next to a release, to be muxed in as an extra track.
+pub struct ExternalSub {
+    path: PathBuf,
+    language: String
+}
+
+impl ExternalSub {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The codec ffmpeg reads the file as, going by its extension. The same names ffprobe gives muxed tracks.
+    pub fn codec(&self) -> &'static str {
+        let extension = self.path.extension().map(| e | e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+        match extension.as_str() {
+            "srt" => "subrip",
+            "sup" => "hdmv_pgs_subtitle",
+            "vtt" => "webvtt",
+            // ffmpeg reads SSA files as ASS too.
+            _ => "ass"
+        }
+    }
+}
+
+/// Finds the subtitles in `dir` that belong to `file`, going by their name starting with the file's.
+/// `Show - 01.eng.ass` belongs to `Show - 01.mkv`, and gets tagged as english.
+pub fn find_external_subs(dir: &Path, file: &Path) -> Vec<ExternalSub> {
+    let mut subs: Vec<ExternalSub> = list_files(dir, &SUB_EXTENSIONS)
+        .into_iter()
+        .filter_map(| path | {
+            let suffix = stem_suffix(file, &path)?;
+            let language = analyze::language_from_file_name(&suffix).unwrap_or("und").to_owned();
+
+            Some(ExternalSub { path, language })
+        })
+        .collect()
+    ;
+
+    subs.sort_by(| a, b | a.path.cmp(&b.path));
+    subs
+}
+
+/// Finds the Matroska audio (.mka) and subtitle (.mks) files next to `file` that belong to it, same naming as external subs.
+/// Returns them along with the language their name mentions, if any.
+pub fn find_sidecars(file: &Path) -> Vec<(PathBuf, Option<&'static str>)> {
+    let dir = match file.parent() {
+        Some(dir) => dir,
+        None => return Vec::new()
+    };
+
+    let mut sidecars: Vec<(PathBuf, Option<&'static str>)> = list_files(dir, &SIDECAR_EXTENSIONS)
+        .into_iter()
+        .filter_map(| path | {
+            let suffix = stem_suffix(file, &path)?;
+            let language = analyze::language_from_file_name(&suffix);
+
+            Some((path, language))
+        })
+        .collect()
+    ;
+
+    sidecars.sort();
+    sidecars
+}
+
+// What comes after `file`'s stem on `other`'s, if `other` is named after `file`.
+fn stem_suffix(file: &Path, other: &Path) -> Option<String> {
+    let stem = file.file_stem()?.to_string_lossy().to_string();
+    let other_stem = other.file_stem()?.to_string_lossy().to_string();
+    let suffix = other_stem.strip_prefix(&stem)?;
+
+    // Keeps "Show - 01" from picking up "Show - 010".
+    if !suffix.is_empty() && !suffix.starts_with(['.', '_', ' ', '-', '[']) {
+        return None;
+    }
+
+    Some(suffix.to_owned())
+}
+
+/// Where the metadata sidecar for `file` would be, filling `pattern`'s {stem} and {name} in with the file's.
+/// Relative patterns start from the file's directory.
+pub fn metadata_sidecar_path(file: &Path, pattern: &str) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+
+    let relative = PathBuf::from(pattern.replace("{stem}", &stem).replace("{name}", &name));
+    file.parent().map(| dir | dir.join(&relative)).unwrap_or(relative)
+}
+
+/// The tags in a JSON metadata sidecar, which has to be a flat object like `{"title": "Episode 1"}`.
+pub fn read_metadata_sidecar(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    let object = match json.as_object() {
+        Some(object) => object,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "the sidecar isn't a JSON object"))
+    };
+
+    let mut metadata = Vec::new();
+
+    for (key, value) in object {
+        let value = match value {
+            serde_json::Value::String(value) => value.clone(),
+            serde_json::Value::Number(_) | serde_json::Value::Bool(_) => value.to_string(),
+            serde_json::Value::Null => continue,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("'{key}' isn't a plain value")))
+        };
+
+        metadata.push((key.clone(), value));
+    }
+
+    Ok(metadata)
+}
+
+/// Every font file in `dir`.
+pub fn find_fonts(dir: &Path) -> Vec<PathBuf> {
+    let mut fonts = list_files(dir, &FONT_EXTENSIONS);
+    fonts.sort();
+    fonts
+}
+
+/// The MIME type ffmpeg needs to attach a font, it won't guess it on its own.
+pub fn font_mime_type(path: &Path) -> &'static str {
+    let extension = path.extension().map(| e | e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    match extension.as_str() {
+        "otf" => "font/otf",
+        "ttc" => "font/collection",
+        _ => "font/ttf"
+    }
+}
+
+/// The codec ffprobe gives a font attachment like `path`, once it's muxed in.
+pub fn font_codec(path: &Path) -> &'static str {
+    let extension = path.extension().map(| e | e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+    match extension.as_str() {
+        "otf" => "otf",
+        _ => "ttf"
+    }
+}
+
+fn list_files(dir: &Path, extensions: &[&str]) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new()
+    };
+
+    entries
+        .filter_map(| entry | entry.ok())
+        .map(| entry | entry.path())
+        .filter(| path | {
+            path.is_file() && path.extension()
+                .map(| e | extensions.contains(&e.to_string_lossy().to_lowercase().as_str()))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+// Formats that can go into an MKV as-is.
+const SUB_EXTENSIONS: [&str; 5] = [
+    "ass",
+    "ssa",
+    "srt",
+    "sup",
+    "vtt"
+];
+
+const SIDECAR_EXTENSIONS: [&str; 2] = [
+    "mka",
+    "mks"
+];
+
+const FONT_EXTENSIONS: [&str; 3] = [
+    "otf",
+    "ttf",
+    "ttc"
+];
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use super::{FFProbeResult, FFProbeStream};
 use super::error::ProbeError;
 
@@ -5,13 +7,14 @@ pub struct MkvFile {
     size: u64,
     duration: f64,
 
+    tags: HashMap<String, String>,
     streams: Vec<Stream>
 }
 
 impl MkvFile {
     pub(super) fn parse_result(probe: FFProbeResult) -> Result<MkvFile, ProbeError> {
         let size = probe.format.size.parse::<u64>().map_err(|_| ProbeError::NumParseError(probe.format.size))?;
-        let duration = probe.format.duration.parse::<f64>().map_err(|_| ProbeError::NumParseError(probe.format.duration))?;
+        let format_duration = probe.format.duration.unwrap_or_else(|| String::from("N/A"));
 
         let mut streams = Vec::new();
 
@@ -19,14 +22,36 @@ impl MkvFile {
             streams.push(Stream::parse_result(stream_probe)?);
         }
 
-        Ok(
-            MkvFile {
-                size,
-                duration,
+        let mut mkv = MkvFile::new(size, 0.0, streams).with_tags(probe.format.tags);
 
-                streams
+        // Without a container duration, the video's is the next best thing. Then, the longest stream.
+        mkv.duration = match parse_duration(&format_duration) {
+            Some(duration) => duration,
+            None => {
+                mkv.primary_video_stream()
+                    .and_then(| (_, s) | s.duration)
+                    .or_else(|| mkv.streams.iter().filter_map(| s | s.duration).reduce(f64::max))
+                    .ok_or(ProbeError::NumParseError(format_duration))?
             }
-        )
+        };
+
+        Ok(mkv)
+    }
+
+    /// A file out of already known streams, for checking the analysis on made-up track layouts without real media.
+    pub fn new(size: u64, duration: f64, streams: Vec<Stream>) -> MkvFile {
+        MkvFile {
+            size,
+            duration,
+
+            tags: HashMap::new(),
+            streams
+        }
+    }
+
+    pub fn with_tags(mut self, tags: HashMap<String, String>) -> MkvFile {
+        self.tags = tags;
+        self
     }
 
     pub fn size(&self) -> u64 {
@@ -37,6 +62,40 @@ impl MkvFile {
         self.duration
     }
 
+    /// Container-level tags (title, encoder, creation_time, etc).
+    pub fn tags(&self) -> &HashMap<String, String> {
+        &self.tags
+    }
+
+    /// Looks up a format-level tag, ignoring case. Returns the tag's original key along with its value.
+    pub fn tag(&self, key: &str) -> Option<(&str, &str)> {
+        self.tags.iter()
+            .find(| (k, _) | k.eq_ignore_ascii_case(key))
+            .map(| (k, v) | (k.as_str(), v.as_str()))
+    }
+
+    /// Tags untagged ("und") audio and subtitle streams as `audio_language` and `sub_language` respectively.
+    /// Returns how many were changed.
+    pub fn fill_missing_languages(&mut self, audio_language: Option<&str>, sub_language: Option<&str>) -> usize {
+        let mut changed = 0;
+
+        for stream in self.streams.iter_mut() {
+            let (language, new_language) = match &mut stream.codec_type {
+                CodecType::Audio { language, .. } => (language, audio_language),
+                CodecType::Subtitle { language, .. } => (language, sub_language),
+                _ => continue
+            };
+
+            if let Some(new_language) = new_language.filter(| _ | language == "und") {
+                *language = new_language.to_owned();
+                stream.language_filled = true;
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+
     pub fn attachments(&self) -> Vec<&Stream> {
         self.streams.iter()
             .filter(| s | {
@@ -56,28 +115,67 @@ impl MkvFile {
     pub fn video_streams(&self) -> Vec<&Stream> {
         self.streams.iter()
             .filter(| s | {
-                matches!(&s.codec_type, CodecType::Video { language: _, title: _ })
+                matches!(&s.codec_type, CodecType::Video { .. })
             })
             .collect()
     }
 
+    /// The file's actual video, skipping over cover art muxed in as a video stream.
+    /// Also returns the stream's index among the file's video streams.
+    pub fn primary_video_stream(&self) -> Option<(usize, &Stream)> {
+        let video_streams = self.video_streams();
+
+        let idx = video_streams.iter()
+            .position(| s | !s.is_cover_art())
+            // If everything looks like a cover, something's off. Go with the first one like before.
+            .unwrap_or_default()
+        ;
+
+        video_streams.get(idx).map(| s | (idx, *s))
+    }
+
     pub fn subtitles_streams(&self) -> Vec<&Stream> {
         self.streams.iter()
             .filter(| s | {
-                matches!(&s.codec_type, CodecType::Subtitle { language: _, title: _ })
+                matches!(&s.codec_type, CodecType::Subtitle { .. })
+            })
+            .collect()
+    }
+
+    pub fn other_streams(&self) -> Vec<&Stream> {
+        self.streams.iter()
+            .filter(| s | {
+                matches!(&s.codec_type, CodecType::Other { codec_type: _ })
             })
             .collect()
     }
 }
 
 pub struct Stream {
+    index: usize,
     codec: String,
     codec_type: CodecType,
+
+    duration: Option<f64>,
+    language_filled: bool,
+
+    default: bool,
+    comment: bool,
+    forced: bool,
+    attached_pic: bool,
 }
 
 impl Stream {
     fn parse_result(probe: FFProbeStream) -> Result<Stream, ProbeError> {
+        let duration = probe.duration
+            .and_then(| d | parse_duration(&d))
+            .or_else(|| probe.tags.duration())
+        ;
+
         let codec_type = {
+            let event_count = probe.tags.statistic("NUMBER_OF_FRAMES");
+            let byte_count = probe.tags.statistic("NUMBER_OF_BYTES");
+
             let title = probe.tags.title.unwrap_or_default();
             let language = probe.tags.language.unwrap_or_else(|| String::from("und"));
 
@@ -86,26 +184,122 @@ impl Stream {
 
             match probe.codec_type.as_str() {
                 "audio" => CodecType::Audio { language, title, channels: probe.channels },
-                "video" => CodecType::Video { language, title },
-                "subtitle" => CodecType::Subtitle { language, title },
+                "video" => {
+                    let frame_count = probe.nb_frames.and_then(| n | n.parse().ok());
+                    let frame_rate = parse_frame_rate(&probe.r_frame_rate);
+                    let avg_frame_rate = parse_frame_rate(&probe.avg_frame_rate);
+
+                    let color = ColorInfo {
+                        transfer: probe.color_transfer,
+                        primaries: probe.color_primaries,
+                        space: probe.color_space
+                    };
+
+                    CodecType::Video { language, title, pix_fmt: probe.pix_fmt, frame_count, frame_rate, avg_frame_rate, color }
+                }
+                "subtitle" => CodecType::Subtitle { language, title, event_count, byte_count },
                 "attachment" => CodecType::Attachment { filename, mime_type },
     
-                _ => return Err(ProbeError::UnknownCodecType(probe.codec_type))
+                // Data streams (timecodes, menus, etc) and whatever else ffprobe comes up with.
+                _ => CodecType::Other { codec_type: probe.codec_type }
             }
         };
 
-        Ok(
-            Stream {
-                codec: probe.codec_name,
-                codec_type,
-            }
-        )
+        let stream = Stream::new(probe.index, &probe.codec_name, codec_type)
+            .with_duration(duration)
+            .with_default(probe.disposition.default != 0)
+            .with_comment(probe.disposition.comment != 0)
+            .with_forced(probe.disposition.forced != 0)
+            .with_attached_pic(probe.disposition.attached_pic != 0)
+        ;
+
+        Ok(stream)
+    }
+
+    /// A stream with everything but its type and codec left empty, the with_* functions fill in the rest.
+    pub fn new(index: usize, codec: &str, codec_type: CodecType) -> Stream {
+        Stream {
+            index,
+            codec: codec.to_owned(),
+            codec_type,
+
+            duration: None,
+            language_filled: false,
+
+            default: false,
+            comment: false,
+            forced: false,
+            attached_pic: false
+        }
+    }
+
+    /// An untagged video stream, with no colour info or frame rates, for tests that only care about the codec and format.
+    #[cfg(test)]
+    pub fn video(index: usize, codec: &str, pix_fmt: &str, frame_count: Option<u64>) -> Stream {
+        let color = ColorInfo { transfer: String::new(), primaries: String::new(), space: String::new() };
+        let codec_type = CodecType::Video {
+            language: String::from("und"),
+            title: String::new(),
+            pix_fmt: pix_fmt.to_owned(),
+            frame_count,
+            frame_rate: None,
+            avg_frame_rate: None,
+            color
+        };
+
+        Stream::new(index, codec, codec_type)
+    }
+
+    pub fn with_duration(mut self, duration: Option<f64>) -> Stream {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_default(mut self, default: bool) -> Stream {
+        self.default = default;
+        self
+    }
+
+    pub fn with_comment(mut self, comment: bool) -> Stream {
+        self.comment = comment;
+        self
+    }
+
+    pub fn with_forced(mut self, forced: bool) -> Stream {
+        self.forced = forced;
+        self
+    }
+
+    pub fn with_attached_pic(mut self, attached_pic: bool) -> Stream {
+        self.attached_pic = attached_pic;
+        self
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
     }
 
     pub fn codec(&self) -> &str {
         self.codec.as_str()
     }
 
+    /// Whether the stream's language was filled in by us, and has to be written to the output.
+    pub fn is_language_filled(&self) -> bool {
+        self.language_filled
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+
+    pub fn is_comment(&self) -> bool {
+        self.comment
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.forced
+    }
+
     pub fn channels(&self) -> u64 {
         if let CodecType::Audio { channels, .. } = self.codec_type {
             channels
@@ -115,12 +309,109 @@ impl Stream {
         }
     }
 
+    pub fn pix_fmt(&self) -> String {
+        if let CodecType::Video { pix_fmt, .. } = &self.codec_type {
+            pix_fmt.clone()
+        }
+        else {
+            String::new()
+        }
+    }
+
+    /// Still images muxed as a video stream, usually cover art.
+    /// Goes by the attached_pic disposition, falling back to single-frame image codecs (mjpeg, png, etc) for muxers that don't set it.
+    pub fn is_cover_art(&self) -> bool {
+        if let CodecType::Video { frame_count, .. } = &self.codec_type {
+            self.attached_pic || (IMAGE_CODECS.contains(&self.codec()) && frame_count.map(| n | n <= 1).unwrap_or(true))
+        }
+        else {
+            false
+        }
+    }
+
+    /// How much a subtitle track has in it, going by the statistics tags mkvmerge writes.
+    /// Event count first, then size. Zero for everything else, or when the tags are missing.
+    pub fn subtitle_weight(&self) -> (u64, u64) {
+        if let CodecType::Subtitle { event_count, byte_count, .. } = self.codec_type {
+            (event_count.unwrap_or_default(), byte_count.unwrap_or_default())
+        }
+        else {
+            (0, 0)
+        }
+    }
+
+    pub fn color(&self) -> Option<&ColorInfo> {
+        if let CodecType::Video { color, .. } = &self.codec_type {
+            Some(color)
+        }
+        else {
+            None
+        }
+    }
+
+    /// PQ (HDR10, Dolby Vision) or HLG video.
+    pub fn is_hdr(&self) -> bool {
+        self.color().map(| c | HDR_TRANSFERS.contains(&c.transfer.as_str())).unwrap_or(false)
+    }
+
+    /// The base frame rate, for video streams that have one.
+    pub fn frame_rate(&self) -> Option<f64> {
+        if let CodecType::Video { frame_rate, .. } = self.codec_type {
+            frame_rate
+        }
+        else {
+            None
+        }
+    }
+
+    /// Likely variable frame rate, going by the base frame rate not matching the average one.
+    pub fn is_variable_frame_rate(&self) -> bool {
+        if let CodecType::Video { frame_rate: Some(frame_rate), avg_frame_rate: Some(avg_frame_rate), .. } = self.codec_type {
+            (frame_rate - avg_frame_rate).abs() > VFR_TOLERANCE
+        }
+        else {
+            false
+        }
+    }
+
+    /// Bits per component, going by the pixel format's name (yuv420p10le, gray12le, p010le, etc).
+    /// Formats above 8-bit always carry an endianness suffix, so anything without one (nv12, yuv410p) is 8-bit.
+    pub fn bit_depth(&self) -> u8 {
+        let pix_fmt = self.pix_fmt();
+
+        let name = match pix_fmt.strip_suffix("le").or_else(|| pix_fmt.strip_suffix("be")) {
+            Some(name) => name,
+            None => return 8
+        };
+
+        let digits = &name[name.trim_end_matches(| c: char | c.is_ascii_digit()).len()..];
+        // The semi-planar and packed formats (p010, p216, y210) put the depth on the last two digits.
+        let depth = if digits.len() == 3 { &digits[1..] } else { digits };
+
+        match depth.parse::<u8>() {
+            Ok(depth @ 9..=16) => depth,
+            // Packed RGB gives the size of the whole pixel (rgb48le, rgba64le).
+            Ok(48 | 64) => 16,
+            _ => 8
+        }
+    }
+
+    pub fn mime_type(&self) -> String {
+        if let CodecType::Attachment { mime_type, .. } = &self.codec_type {
+            mime_type.clone()
+        }
+        else {
+            String::new()
+        }
+    }
+
     pub fn stream_title(&self) -> String {
         match &self.codec_type {
             CodecType::Audio { title, .. } => title.clone(),
             CodecType::Video { title, .. } => title.clone(),
             CodecType::Subtitle { title, .. } => title.clone(),
             CodecType::Attachment { filename, .. } => filename.clone(),
+            CodecType::Other { .. } => String::new(),
         }
     }
 
@@ -134,10 +425,133 @@ impl Stream {
     }
 }
 
+/// A video stream's colour tags, empty when the source doesn't say.
+#[derive(PartialEq)]
+pub struct ColorInfo {
+    pub transfer: String,
+    pub primaries: String,
+    pub space: String
+}
+
+// ffprobe gives frame rates as fractions (24000/1001), with 0/0 when it doesn't know.
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/')?;
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+
+    if num == 0.0 || den == 0.0 {
+        None
+    }
+    else {
+        Some(num / den)
+    }
+}
+
 #[derive(PartialEq)]
 pub enum CodecType {
     Audio { language: String, title: String, channels: u64 },
-    Video { language: String, title: String },
-    Subtitle { language: String, title: String },
-    Attachment { filename: String, mime_type: String }
+    Video {
+        language: String,
+        title: String,
+        pix_fmt: String,
+        frame_count: Option<u64>,
+        frame_rate: Option<f64>,
+        avg_frame_rate: Option<f64>,
+        color: ColorInfo
+    },
+    Subtitle { language: String, title: String, event_count: Option<u64>, byte_count: Option<u64> },
+    Attachment { filename: String, mime_type: String },
+    Other { codec_type: String }
+}
+
+/// A duration in seconds as ffprobe prints it, which is usually "5220.000000" but can also be "5220", "5.22e3" or "N/A".
+/// None for anything that isn't a usable length.
+fn parse_duration(value: &str) -> Option<f64> {
+    let value = value.trim();
+
+    if value.is_empty() || value.eq_ignore_ascii_case("N/A") {
+        return None;
+    }
+
+    // "inf" and "nan" parse just fine, but there's no encoding a file that long.
+    value.parse::<f64>().ok().filter(| duration | duration.is_finite() && *duration >= 0.0)
+}
+
+const IMAGE_CODECS: [&str; 5] = [
+    "bmp",
+    "gif",
+    "png",
+    "webp",
+    "mjpeg"
+];
+
+const HDR_TRANSFERS: [&str; 2] = [
+    "smpte2084",
+    "arib-std-b67"
+];
+
+// How far apart (in fps) the base and average frame rates can be before calling it VFR.
+const VFR_TOLERANCE: f64 = 0.01;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bit_depth(pix_fmt: &str) -> u8 {
+        Stream::video(0, "hevc", pix_fmt, None).bit_depth()
+    }
+
+    #[test]
+    fn bit_depth_reads_the_suffix() {
+        assert_eq!(bit_depth("yuv420p10le"), 10);
+        assert_eq!(bit_depth("yuv444p12be"), 12);
+        assert_eq!(bit_depth("gray16le"), 16);
+        assert_eq!(bit_depth("p010le"), 10);
+        assert_eq!(bit_depth("rgb48le"), 16);
+    }
+
+    #[test]
+    fn bit_depth_ignores_digits_in_8_bit_names() {
+        assert_eq!(bit_depth("yuv420p"), 8);
+        assert_eq!(bit_depth("nv12"), 8);
+        assert_eq!(bit_depth("nv16"), 8);
+        assert_eq!(bit_depth("yuv410p"), 8);
+        assert_eq!(bit_depth("rgb565le"), 8);
+    }
+
+    #[test]
+    fn cover_art_first_is_skipped() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            Stream::video(0, "mjpeg", "yuv420p", Some(1)),
+            Stream::video(1, "h264", "yuv420p", Some(34000))
+        ]);
+
+        let (idx, stream) = mkv.primary_video_stream().unwrap();
+
+        assert_eq!(idx, 1);
+        assert_eq!(stream.codec(), "h264");
+    }
+
+    #[test]
+    fn attached_pic_first_is_skipped() {
+        let mkv = MkvFile::new(0, 0.0, vec![
+            Stream::video(0, "h264", "yuv420p", Some(1)).with_attached_pic(true),
+            Stream::video(1, "hevc", "yuv420p", None)
+        ]);
+
+        assert_eq!(mkv.primary_video_stream().map(| (idx, _) | idx), Some(1));
+    }
+
+    #[test]
+    fn duration_parses_plain_and_integer_values() {
+        assert_eq!(parse_duration("5220.000000"), Some(5220.0));
+        assert_eq!(parse_duration(" 5220 "), Some(5220.0));
+        assert_eq!(parse_duration("5.22e3"), Some(5220.0));
+    }
+
+    #[test]
+    fn duration_is_none_when_missing() {
+        assert_eq!(parse_duration("N/A"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("inf"), None);
+    }
 }
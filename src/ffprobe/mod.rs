@@ -1,12 +1,17 @@
 pub mod mkv;
 pub mod error;
 
-use std::path::Path;
+use std::sync::Mutex;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use serde::Deserialize;
 use error::ProbeError;
 
+use crate::args::AppArgs;
+
 #[derive(Deserialize)]
 struct FFProbeResult {
     format: FFProbeFormat,
@@ -15,6 +20,8 @@ struct FFProbeResult {
 
 #[derive(Deserialize)]
 struct FFProbeStream {
+    index: usize,
+
     #[serde(default)]
     codec_name: String,
     codec_type: String,
@@ -23,7 +30,41 @@ struct FFProbeStream {
     channels: u64,
 
     #[serde(default)]
-    tags: FFProbeStreamTags
+    duration: Option<String>,
+
+    #[serde(default)]
+    pix_fmt: String,
+    #[serde(default)]
+    nb_frames: Option<String>,
+    #[serde(default)]
+    r_frame_rate: String,
+    #[serde(default)]
+    avg_frame_rate: String,
+
+    #[serde(default)]
+    color_transfer: String,
+    #[serde(default)]
+    color_primaries: String,
+    #[serde(default)]
+    color_space: String,
+
+    #[serde(default)]
+    tags: FFProbeStreamTags,
+
+    #[serde(default)]
+    disposition: FFProbeDisposition
+}
+
+#[derive(Deserialize, Default)]
+struct FFProbeDisposition {
+    #[serde(default)]
+    default: u8,
+    #[serde(default)]
+    comment: u8,
+    #[serde(default)]
+    forced: u8,
+    #[serde(default)]
+    attached_pic: u8,
 }
 
 #[derive(Deserialize, Default)]
@@ -33,17 +74,100 @@ struct FFProbeStreamTags {
 
     filename: Option<String>,
     mimetype: Option<String>,
+
+    // Statistics tags written by mkvmerge, like NUMBER_OF_FRAMES-eng.
+    #[serde(flatten)]
+    other: HashMap<String, String>,
+}
+
+impl FFProbeStreamTags {
+    fn statistic(&self, name: &str) -> Option<u64> {
+        self.other.iter()
+            .find(| (key, _) | key.split('-').next() == Some(name))
+            .and_then(| (_, value) | value.parse().ok())
+    }
+
+    /// The stream's DURATION tag (HH:MM:SS.nnnnnnnnn) in seconds.
+    fn duration(&self) -> Option<f64> {
+        let value = self.other.iter()
+            .find(| (key, _) | key.split('-').next().map(| k | k.eq_ignore_ascii_case("DURATION")).unwrap_or(false))
+            .map(| (_, value) | value)?
+        ;
+
+        let mut parts = value.split(':');
+        let hours: f64 = parts.next()?.parse().ok()?;
+        let minutes: f64 = parts.next()?.parse().ok()?;
+        let seconds: f64 = parts.next()?.parse().ok()?;
+
+        Some(hours * 3600.0 + minutes * 60.0 + seconds)
+    }
 }
 
 #[derive(Deserialize)]
 struct FFProbeFormat {
-    duration: String,
+    // Missing, or N/A, on some files. The streams usually still know theirs.
+    #[serde(default)]
+    duration: Option<String>,
     size: String,
+
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// How much of a file ffprobe (and ffmpeg) read before deciding on its streams.
+#[derive(Copy, Clone)]
+pub struct ProbeOptions {
+    probe_size: Option<u64>,
+    analyze_duration: Option<u64>
+}
+
+impl ProbeOptions {
+    pub fn new(cfg: &AppArgs) -> ProbeOptions {
+        ProbeOptions {
+            probe_size: cfg.probe_size(),
+            analyze_duration: cfg.analyze_duration()
+        }
+    }
+
+    /// Input options shared by ffprobe and ffmpeg, they go before the -i they apply to.
+    pub fn arguments(&self) -> Vec<String> {
+        let mut arguments = Vec::new();
+
+        if let Some(probe_size) = self.probe_size {
+            arguments.push(String::from("-probesize"));
+            arguments.push(probe_size.to_string());
+        }
+
+        // ffmpeg wants microseconds here.
+        if let Some(analyze_duration) = self.analyze_duration {
+            arguments.push(String::from("-analyzeduration"));
+            arguments.push((analyze_duration * 1_000_000).to_string());
+        }
+
+        arguments
+    }
+}
+
+pub fn probe_file(path: &Path, options: &ProbeOptions) -> Result<mkv::MkvFile, ProbeError> {
+    probe(path, options, false)
+}
+
+/// Like probe_file, but only the video streams make it to the result. Quicker on files with loads of tracks,
+/// and plenty when all that matters is the container (size, duration) and the video. All of them are kept,
+/// so primary_video_stream can still skip cover art muxed ahead of the real video.
+pub fn probe_video(path: &Path, options: &ProbeOptions) -> Result<mkv::MkvFile, ProbeError> {
+    probe(path, options, true)
 }
 
-pub fn probe_file(path: &Path) -> Result<mkv::MkvFile, ProbeError> {
+fn probe(path: &Path, options: &ProbeOptions, video_only: bool) -> Result<mkv::MkvFile, ProbeError> {
     let mut ffprobe = Command::new("ffprobe");
     ffprobe.args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_streams"]);
+
+    if video_only {
+        ffprobe.args(["-select_streams", "v"]);
+    }
+
+    ffprobe.args(options.arguments());
     ffprobe.arg(path);
 
     let output = ffprobe.output().map_err(ProbeError::ExecError)?;
@@ -51,3 +175,22 @@ pub fn probe_file(path: &Path) -> Result<mkv::MkvFile, ProbeError> {
 
     mkv::MkvFile::parse_result(probe)
 }
+
+/// Probes every file in `paths`, `max_concurrent` at a time. ffprobe spends most of its time waiting on the disk.
+pub fn probe_files(paths: &[PathBuf], options: &ProbeOptions, max_concurrent: usize, video_only: bool) -> HashMap<PathBuf, Result<mkv::MkvFile, ProbeError>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(HashMap::with_capacity(paths.len()));
+
+    std::thread::scope(| scope | {
+        for _ in 0..max_concurrent.min(paths.len()) {
+            scope.spawn(|| {
+                while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = probe(path, options, video_only);
+                    results.lock().unwrap().insert(path.clone(), result);
+                }
+            });
+        }
+    });
+
+    results.into_inner().unwrap()
+}
@@ -0,0 +1,29 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Decodes the whole file without writing anything, returning whatever errors ffmpeg ran into along the way.
+/// An empty list means the file decoded cleanly.
+pub fn decode_errors(file: &Path) -> io::Result<Vec<String>> {
+    let result = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-v", "error"])
+        .arg("-i")
+        .arg(crate::absolute_path(file))
+        .args(["-f", "null", "-"])
+        .output()?
+    ;
+
+    let mut errors: Vec<String> = String::from_utf8_lossy(&result.stderr)
+        .lines()
+        .map(| line | line.trim().to_owned())
+        .filter(| line | !line.is_empty())
+        .collect()
+    ;
+
+    // Failing without a word is still a failure.
+    if !result.status.success() && errors.is_empty() {
+        errors.push(format!("ffmpeg exited with {}", result.status));
+    }
+
+    Ok(errors)
+}
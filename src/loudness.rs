@@ -0,0 +1,64 @@
+use std::path::Path;
+use std::process::Command;
+
+use serde::Deserialize;
+
+/// What the first loudnorm pass found out about a track. loudnorm prints every value as a string.
+#[derive(Deserialize)]
+pub struct LoudnessMeasurement {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String
+}
+
+/// Runs the first loudnorm pass over audio stream `stream_idx` of `file`, after `pre_filters` (like a downmix) if any.
+/// `channels` should match the output's, so the measurement is taken on the same mix the second pass gets.
+pub fn measure_loudness(file: &Path, stream_idx: usize, pre_filters: &[String], channels: u64, target: f64) -> Option<LoudnessMeasurement> {
+    let mut filters = pre_filters.to_vec();
+    filters.push(format!("loudnorm=I={target}:TP={TRUE_PEAK}:LRA={LOUDNESS_RANGE}:print_format=json"));
+
+    // The measurement only shows up at the info log level, on stderr.
+    let result = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-loglevel", "info"])
+        .arg("-i")
+        .arg(crate::absolute_path(file))
+        .args(["-map", &format!("0:a:{stream_idx}")])
+        .args(["-af", &filters.join(",")])
+        .args(["-ac", &channels.to_string()])
+        .args(["-f", "null", "-"])
+        .output()
+        .ok()?
+    ;
+
+    let stderr = String::from_utf8_lossy(&result.stderr);
+
+    // The JSON block is the last thing loudnorm prints.
+    let start = stderr.rfind('{')?;
+    let end = stderr.rfind('}')?;
+
+    serde_json::from_str(stderr.get(start..=end)?).ok()
+}
+
+/// The loudnorm filter for a track. Single pass without a measurement, linear normalization with one.
+pub fn loudnorm_filter(target: f64, measurement: Option<&LoudnessMeasurement>) -> String {
+    let mut filter = format!("loudnorm=I={target}:TP={TRUE_PEAK}:LRA={LOUDNESS_RANGE}");
+
+    if let Some(m) = measurement {
+        filter.push_str(&format!(
+            ":measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            m.input_i,
+            m.input_tp,
+            m.input_lra,
+            m.input_thresh,
+            m.target_offset
+        ));
+    }
+
+    filter
+}
+
+// EBU R128's recommendations, the integrated target is configurable.
+const TRUE_PEAK: f64 = -1.5;
+const LOUDNESS_RANGE: f64 = 11.0;
@@ -1,40 +1,146 @@
 mod args;
+mod analyze;
+mod disk;
+mod encoder;
+mod external;
 mod ffprobe;
+mod integrity;
+mod loudness;
+mod manifest;
+mod priority;
+mod progress;
+mod quality;
+mod rename;
+mod report;
+mod segments;
+mod sweep;
+mod thermal;
 
 use std::fs;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::process::Command;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
 
 use log::*;
 use flexi_logger::{Logger, LoggerHandle};
-use indicatif::{ProgressBar, ProgressStyle};
 
 use clap::Parser;
 use walkdir::WalkDir;
 use bytesize::ByteSize;
 
-use args::{PreloadMode, TranscodeMode};
+use args::{BurnSubs, Downmix, FrameRate, HashAlgorithm, HwAccel, OpusBitrate, OutputBitDepth, OverwritePolicy, PreloadMode, QualityMetric, ReportFormat, SortOrder, SvtLogLevel, Tonemap, TrackAssertion, TrackKind, TrackOrder, TranscodeMode, VfrMode};
+use analyze::{CrunchPlan, TrackFilters};
+use encoder::EncoderSettings;
+use manifest::Manifest;
+use progress::FileProgress;
+use segments::SegmentedEncode;
+use report::{FileReport, FileStatus};
+use ffprobe::ProbeOptions;
+use ffprobe::error::ProbeError;
 use ffprobe::mkv::{MkvFile, Stream};
 
 pub struct Cruncher {
+    inputs: Vec<PathBuf>,
     output: PathBuf,
     intermediate: Option<PathBuf>,
+    intermediate_only_transcodes: bool,
+    temp_dir: Option<PathBuf>,
+    // The intermediate file being written right now, removed if the run dies halfway through it.
+    current_target: Option<PathBuf>,
 
     files: Vec<PathBuf>,
+    // Results from probing up front, with --max-concurrent-probes.
+    probes: HashMap<PathBuf, Result<MkvFile, ProbeError>>,
+    flatten: bool,
+    overwrite: OverwritePolicy,
+    min_duration: Option<f64>,
+    precheck: bool,
 
     preload_mode: PreloadMode,
-    transcode_mode: TranscodeMode
+    remote_input: bool,
+    transcode_mode: TranscodeMode,
+    audio_mode: TranscodeMode,
+    keep_lossless: bool,
+    opus_bitrate: Option<OpusBitrate>,
+    downmix: Downmix,
+    loudnorm: Option<f64>,
+    loudnorm_2pass: bool,
+    output_bit_depth: OutputBitDepth,
+    vfr: VfrMode,
+    tonemap: Tonemap,
+    fps: Option<FrameRate>,
+    burn_subs: Option<BurnSubs>,
+
+    probe_options: ProbeOptions,
+    filters: TrackFilters,
+    infer_lang_from_filename: bool,
+    default_audio_lang: Option<String>,
+    default_sub_lang: Option<String>,
+    priority_sub_lang: String,
+    track_order: TrackOrder,
+    strict: bool,
+    fail_fast: bool,
+    assertions: Vec<TrackAssertion>,
+    external_subs: Option<PathBuf>,
+    external_fonts: Vec<PathBuf>,
+    sidecars: bool,
+
+    encoder: EncoderSettings,
+    hwaccel: Option<HwAccel>,
+    svt_log_level: Option<String>,
+    nice: Option<i32>,
+    segmented: Option<u8>,
+    cooldown: Option<u64>,
+    max_temp: Option<f64>,
+
+    crf_sweep: Vec<u8>,
+    sample_length: u64,
+
+    animated_progress: bool,
+    measure_quality: Option<QualityMetric>,
+    keep_smaller: bool,
+    keep_original: bool,
+    min_savings: Option<f64>,
+    keep_metadata: Vec<String>,
+    metadata_from: Option<String>,
+    set_title_from_filename: bool,
+    deterministic: bool,
+    rename_only: bool,
+    dry_run: bool,
+    copy_timestamps: bool,
+    preserve_permissions: bool,
+    hash: HashAlgorithm,
+    hash_sidecar: bool,
+    report: Option<PathBuf>,
+    report_format: ReportFormat,
+
+    manifest: Manifest,
+    // The real stdout, when the output goes there.
+    stdout_output: Option<fs::File>
 }
 
 impl Cruncher {
-    fn init(cfg: args::AppArgs) -> Cruncher {
-        if !cfg.input_dir().exists() {
-            panic!("Input directory doesn't exist!");
+    fn init(cfg: args::AppArgs, stdout_output: Option<fs::File>) -> Cruncher {
+        validate_input_dirs(&cfg.input_dirs());
+
+        let to_stdout = stdout_output.is_some();
+
+        if let Some(tool) = cfg.hash().tool().filter(| tool | !disk::has_tool(tool)) {
+            panic!("--hash {} needs {tool}, which isn't on the PATH!", format!("{:?}", cfg.hash()).to_lowercase());
+        }
+
+        if cfg.max_temp().is_some() && thermal::cpu_temperature().is_none() {
+            warn!("Can't read the CPU temperature on this machine, --max-temp won't do anything.");
         }
 
         if let Some(intermediate) = cfg.intermediate_dir().as_ref() {
+            if intermediate.exists() && !intermediate.is_dir() {
+                panic!("Intermediate path {} is not a directory!", intermediate.to_string_lossy());
+            }
+
             if !intermediate.exists() {
                 if let Err(e) = fs::create_dir_all(intermediate) {
                     panic!("Failed to create intermediate directory! {e}");
@@ -45,8 +151,27 @@ impl Cruncher {
             }
         }
 
-        if !cfg.output_dir().exists() {
-            if let Err(e) = fs::create_dir_all(&cfg.output_dir()) {
+        if let Some(temp_dir) = cfg.temp_dir().as_ref() {
+            if temp_dir.exists() && !temp_dir.is_dir() {
+                panic!("Temp path {} is not a directory!", temp_dir.to_string_lossy());
+            }
+
+            if !temp_dir.exists() {
+                if let Err(e) = fs::create_dir_all(temp_dir) {
+                    panic!("Failed to create temp directory! {e}");
+                }
+                else {
+                    info!("Created temp directory at {}", temp_dir.to_string_lossy())
+                }
+            }
+        }
+
+        if !to_stdout && cfg.output_dir().exists() && !cfg.output_dir().is_dir() {
+            panic!("Output path {} is not a directory!", cfg.output_dir().to_string_lossy());
+        }
+
+        if !to_stdout && !cfg.output_dir().exists() {
+            if let Err(e) = fs::create_dir_all(cfg.output_dir()) {
                 panic!("Failed to create output directory! {e}");
             }
             else {
@@ -54,56 +179,1030 @@ impl Cruncher {
             }
         }
 
-        info!("Reading directory {}", cfg.input_dir().as_os_str().to_string_lossy());
+        let mut files = find_files(&cfg);
 
-        let files = WalkDir::new(&cfg.input_dir())
-            .max_depth(1)
-            .sort_by(|a,b| a.file_name().to_ascii_lowercase().cmp(&b.file_name().to_ascii_lowercase()))
-            .into_iter()
-            .filter_map(| entry | entry.ok())
-            .filter(| entry | entry.file_type().is_file())
-            .filter(| entry | entry.file_name().to_string_lossy().contains(".mkv"))
-            .map(| entry | entry.into_path())
-            .collect::<Vec<PathBuf>>()
+        {
+            let mut names = HashSet::new();
+
+            // Two files with the same name on different subfolders (or input directories) would end up overwriting each other.
+            files.retain(| file | {
+                let name = relative_output_path(&cfg.input_dirs(), file, cfg.flatten());
+
+                if names.insert(name) {
+                    true
+                }
+                else {
+                    warn!("Skipping '{}', another file with the same name is already in the batch.", file.to_string_lossy());
+                    false
+                }
+            });
+        }
+
+        files.retain(| file | {
+            let partial = disk::is_partial_download(file);
+
+            if partial {
+                warn!("Skipping '{}', download in progress.", file.to_string_lossy());
+            }
+
+            !partial
+        });
+
+        // Anything still growing is being written by something else, crunching it now would give a truncated output.
+        // Only files touched recently can be, the rest don't hold up the start (or a dry run) at all.
+        let recent: Vec<(PathBuf, u64)> = files.iter()
+            .filter_map(| file | fs::metadata(file).ok().map(| m | (file, m)))
+            .filter(| (_, metadata) | {
+                metadata.modified()
+                    .ok()
+                    .and_then(| modified | modified.elapsed().ok())
+                    .map(| age | age < Duration::from_secs(RECENT_WRITE_WINDOW))
+                    .unwrap_or(true)
+            })
+            .map(| (file, metadata) | (file.clone(), metadata.len()))
+            .collect()
         ;
 
+        if !recent.is_empty() {
+            std::thread::sleep(Duration::from_secs(GROWTH_CHECK_INTERVAL));
+
+            let growing: HashSet<PathBuf> = recent.into_iter()
+                .filter(| (file, size) | fs::metadata(file).map(| m | m.len()).ok() != Some(*size))
+                .map(| (file, _) | file)
+                .collect()
+            ;
+
+            files.retain(| file | {
+                let growing = growing.contains(file);
+
+                if growing {
+                    warn!("Skipping '{}', still being written (in progress).", file.to_string_lossy());
+                }
+
+                !growing
+            });
+        }
+
+        // There's only one stdout to write to.
+        if to_stdout && files.len() != 1 {
+            panic!("Writing to stdout needs exactly one input file, found {}!", files.len());
+        }
+
+        // A dry run shouldn't wipe the manifest of a batch it's only looking at.
+        let manifest = {
+            if to_stdout {
+                Manifest::disabled()
+            }
+            else {
+                match Manifest::open(&cfg.output_dir(), cfg.resume() || cfg.dry_run()) {
+                    Ok(manifest) => manifest,
+                    Err(e) => panic!("Failed to open the manifest! {e}")
+                }
+            }
+        };
+
+        if cfg.resume() {
+            let file_count = files.len();
+
+            // Anything not in the manifest gets redone, including a file that was interrupted mid-encode.
+            files.retain(| file | {
+                let relative_path = relative_output_path(&cfg.input_dirs(), file, cfg.flatten());
+                let done = manifest.contains(&relative_path.to_string_lossy()) && cfg.output_dir().join(&relative_path).exists();
+
+                !done
+            });
+
+            info!("Resuming batch, {}/{file_count} files already processed.", file_count - files.len());
+        }
+
+        let mut probes = HashMap::new();
+        let probe_options = ProbeOptions::new(&cfg);
+
+        // A CRF sweep only ever looks at the video.
+        let video_only = !cfg.crf_sweep().is_empty();
+
+        // Sorting by duration needs every file probed, that's the only time --limit can't go first.
+        if let Some(max_concurrent) = cfg.max_concurrent_probes().filter(| _ | matches!(cfg.sort(), SortOrder::Duration)) {
+            info!("Probing {} files, {max_concurrent} at a time...", files.len());
+            probes = ffprobe::probe_files(&files, &probe_options, max_concurrent as usize, video_only);
+        }
+
+        match cfg.sort() {
+            SortOrder::Name => {}
+            SortOrder::Size => files.sort_by_cached_key(| file | fs::metadata(file).map(| m | m.len()).unwrap_or_default()),
+            SortOrder::SizeDesc => files.sort_by_cached_key(| file | Reverse(fs::metadata(file).map(| m | m.len()).unwrap_or_default())),
+            SortOrder::Duration => {
+                if probes.is_empty() {
+                    info!("Probing {} files to sort them by duration...", files.len());
+                }
+
+                files.sort_by_cached_key(| file | {
+                    let duration = match probes.get(file) {
+                        Some(probe) => probe.as_ref().map(| mkv | mkv.duration()).ok(),
+                        // The duration's on the container, there's no need to go through every stream for it.
+                        None => ffprobe::probe_video(file, &probe_options).map(| mkv | mkv.duration()).ok()
+                    };
+
+                    (duration.unwrap_or_default() * 1000.0) as u64
+                });
+            }
+        }
+
+        if let Some(limit) = cfg.limit() {
+            if files.len() > limit {
+                info!("Limiting this run to {limit} files, {} will be left for later.", files.len() - limit);
+                files.truncate(limit);
+                probes.retain(| file, _ | files.contains(file));
+            }
+        }
+
+        if let Some(max_concurrent) = cfg.max_concurrent_probes().filter(| _ | probes.is_empty()) {
+            info!("Probing {} files, {max_concurrent} at a time...", files.len());
+            probes = ffprobe::probe_files(&files, &probe_options, max_concurrent as usize, video_only);
+        }
+
+        let remote_input = cfg.remote_input() || cfg.input_dirs().iter().any(| dir | disk::is_network_path(dir));
+
+        if remote_input && !cfg.remote_input() {
+            info!("Input is on a network mount, disabling preload and full hash checks.");
+        }
+
         Cruncher {
+            inputs: cfg.input_dirs(),
             output: cfg.output_dir(),
-            intermediate: cfg.intermediate_dir(),
+            intermediate: cfg.intermediate_dir().filter(| _ | !to_stdout),
+            intermediate_only_transcodes: cfg.intermediate_only_transcodes(),
+            temp_dir: cfg.temp_dir().map(| dir | absolute_path(&dir)),
+            current_target: None,
 
             files,
+            probes,
+            flatten: cfg.flatten(),
+            overwrite: cfg.overwrite(),
+            min_duration: cfg.min_duration(),
+            precheck: cfg.precheck(),
             preload_mode: cfg.preload_mode(),
+            remote_input,
             transcode_mode: cfg.transcode_mode(),
+            audio_mode: cfg.audio_mode(),
+            keep_lossless: cfg.keep_lossless(),
+            opus_bitrate: cfg.opus_bitrate(),
+            downmix: cfg.downmix(),
+            loudnorm: cfg.loudnorm(),
+            loudnorm_2pass: cfg.loudnorm_2pass(),
+            output_bit_depth: cfg.output_bit_depth(),
+            vfr: cfg.vfr(),
+            tonemap: cfg.tonemap(),
+            fps: cfg.fps(),
+            burn_subs: cfg.burn_subs(),
+
+            probe_options: ProbeOptions::new(&cfg),
+            filters: TrackFilters::new(&cfg),
+            infer_lang_from_filename: cfg.infer_lang_from_filename(),
+            default_audio_lang: cfg.default_audio_lang(),
+            default_sub_lang: cfg.default_sub_lang(),
+            priority_sub_lang: cfg.priority_sub_lang(),
+            track_order: cfg.reorder_tracks(),
+            strict: cfg.strict(),
+            fail_fast: cfg.fail_fast(),
+            assertions: cfg.assertions(),
+            external_subs: cfg.external_subs(),
+            external_fonts: cfg.external_fonts().map(| dir | external::find_fonts(&dir)).unwrap_or_default(),
+            sidecars: cfg.sidecars(),
+
+            encoder: EncoderSettings::new(&cfg),
+            hwaccel: cfg.hwaccel().filter(| hwaccel | {
+                let available = encoder::hwaccel_available(*hwaccel);
+
+                if !available {
+                    warn!("Failed to set up {} for decoding, falling back to software decode.", hwaccel.as_str());
+                }
+
+                available
+            }),
+            svt_log_level: svt_log_level(cfg.svt_log_level()),
+            nice: cfg.nice(),
+            segmented: cfg.segmented(),
+            cooldown: cfg.cooldown(),
+            max_temp: cfg.max_temp(),
+
+            crf_sweep: cfg.crf_sweep(),
+            sample_length: cfg.sample_length(),
+
+            // An animated bar is just noise when redirected to a file or a CI log.
+            animated_progress: !cfg.no_progress() && std::io::stdout().is_terminal(),
+            measure_quality: cfg.measure_quality(),
+            keep_smaller: cfg.keep_smaller(),
+            keep_original: cfg.keep_original(),
+            min_savings: cfg.min_savings(),
+            keep_metadata: cfg.keep_metadata(),
+            metadata_from: cfg.metadata_from(),
+            set_title_from_filename: cfg.set_title_from_filename(),
+            deterministic: cfg.deterministic(),
+            rename_only: cfg.rename_only(),
+            dry_run: cfg.dry_run(),
+            copy_timestamps: cfg.copy_timestamps(),
+            preserve_permissions: cfg.preserve_permissions(),
+            hash: cfg.hash(),
+            hash_sidecar: cfg.hash_sidecar(),
+            report: cfg.report(),
+            report_format: cfg.report_format(),
+
+            manifest,
+            stdout_output
+        }
+    }
+
+    /// Asks before runs that would overwrite the source files. Returns whether to go ahead.
+    fn confirm_run(&self, assume_yes: bool) -> bool {
+        let same_dir = self.inputs.iter().any(| input | {
+            match (input.canonicalize(), self.output.canonicalize()) {
+                (Ok(input), Ok(output)) => input == output,
+                _ => false
+            }
+        });
+
+        if !same_dir || assume_yes || self.dry_run {
+            return true;
+        }
+
+        warn!(
+            "About to process {} files, writing the outputs over the sources in {}.",
+            self.files.len(),
+            self.output.to_string_lossy()
+        );
+
+        if !std::io::stdin().is_terminal() {
+            error!("Not running interactively, pass --yes to go ahead anyway.");
+            return false;
+        }
+
+        print!("Continue? [y/N] ");
+        std::io::stdout().flush().ok();
+
+        let mut answer = String::new();
+
+        if std::io::stdin().read_line(&mut answer).is_err() {
+            return false;
+        }
+
+        matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    }
+
+    fn should_overwrite(&self, source: &Path, output: &Path) -> bool {
+        if !output.exists() {
+            return true;
+        }
+
+        match self.overwrite {
+            OverwritePolicy::Always => true,
+            OverwritePolicy::Never => false,
+            OverwritePolicy::Newer => {
+                let source_modified = fs::metadata(source).and_then(| m | m.modified());
+                let output_modified = fs::metadata(output).and_then(| m | m.modified());
+
+                match (source_modified, output_modified) {
+                    (Ok(source), Ok(output)) => source > output,
+                    // Can't tell, so play it safe and redo it.
+                    _ => true
+                }
+            }
+        }
+    }
+
+    /// Whether the source is worth more than an output of `output_size`, under --keep-smaller and --min-savings.
+    fn keeps_source(&self, source_size: u64, output_size: u64, transcoded: bool) -> bool {
+        if self.keep_smaller && output_size > source_size {
+            info!(
+                "  Output ({}) ended up bigger than the source ({}), keeping the source instead.",
+                ByteSize::b(output_size),
+                ByteSize::b(source_size)
+            );
+
+            return true;
+        }
+
+        if let Some(min_savings) = self.min_savings.filter(| _ | transcoded && source_size > 0) {
+            let savings = (1.0 - output_size as f64 / source_size as f64) * 100.0;
+
+            // Not worth the quality loss from a transcode.
+            if savings < min_savings {
+                info!(
+                    "  Output ({}) only saves {savings:.1}% over the source ({}), keeping the source instead.",
+                    ByteSize::b(output_size),
+                    ByteSize::b(source_size)
+                );
+
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Everything a finished output at `output_path` gets, whether it was encoded or copied:
+    /// the source's permissions, the --keep-original copy and the hash. Returns the hash, when one was asked for.
+    /// `kept_source` is for outputs that are the source itself or a copy of it, which already have all of that covered.
+    fn finish_output(&self, file: &Path, output_path: &Path, kept_source: bool) -> Option<String> {
+        // fs::copy already carries them over when the source was kept.
+        if self.preserve_permissions && !kept_source {
+            let permissions = fs::metadata(file).map(| m | m.permissions());
+
+            if let Err(e) = permissions.and_then(| p | fs::set_permissions(output_path, p)) {
+                warn!("  Failed to copy the source's permissions to the output: {e}");
+            }
+        }
+
+        // Pointless when the output already is the source.
+        if self.keep_original && !kept_source && !same_file(file, output_path) {
+            let original_path = original_copy_path(output_path, file);
+
+            match fs::copy(file, &original_path) {
+                Ok(_) => {
+                    info!("  Output: {}", output_path.to_string_lossy());
+                    info!("  Original: {}", original_path.to_string_lossy());
+                }
+                Err(e) => warn!("  Failed to copy the original next to the output: {e}")
+            }
+        }
+
+        // Reading the whole output back is only worth it when the hash goes somewhere.
+        let output_hash = match (self.report.is_some() || self.hash_sidecar).then(|| disk::hash_file(output_path, self.hash)) {
+            Some(Ok(hash)) => hash,
+            Some(Err(e)) => {
+                warn!("  Failed to hash the output: {e}");
+                None
+            }
+            None => None
+        };
+
+        if let Some(hash) = output_hash.as_ref().filter(| _ | self.hash_sidecar) {
+            if let Err(e) = disk::write_hash_sidecar(output_path, self.hash, hash) {
+                warn!("  Failed to write the output's hash next to it: {e}");
+            }
+        }
+
+        output_hash
+    }
+
+    /// Checks there's room for an output of (at most) `size` bytes, plus its copy on `intermediate` if used.
+    fn has_space_for(&self, size: u64, intermediate: Option<&PathBuf>) -> bool {
+        let mut needed = vec![(self.output.as_path(), size)];
+
+        if let Some(intermediate) = intermediate {
+            if disk::same_filesystem(intermediate, &self.output) {
+                needed[0].1 += size;
+            }
+            else {
+                needed.push((intermediate.as_path(), size));
+            }
+        }
+
+        for (dir, size) in needed {
+            if let Some(available) = disk::available_space(dir) {
+                if available < size {
+                    warn!(
+                        "  Not enough space on {} ({} free, {} needed), skipping.\n",
+                        dir.to_string_lossy(),
+                        ByteSize::b(available),
+                        ByteSize::b(size)
+                    );
+
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Waits out --cooldown, then --max-temp until the CPU gets back under it.
+    fn cool_down(&self) {
+        if let Some(cooldown) = self.cooldown {
+            info!("Cooling down for {}...", format_time(cooldown));
+            std::thread::sleep(Duration::from_secs(cooldown));
+        }
+
+        let max_temp = match self.max_temp {
+            Some(max_temp) => max_temp,
+            None => return
+        };
+
+        let temperature = match thermal::cpu_temperature() {
+            Some(temperature) if temperature > max_temp => temperature,
+            _ => return
+        };
+
+        info!("CPU is at {temperature:.0}°C, over the {max_temp:.0}°C limit. Pausing until it cools down...");
+
+        // Some headroom, or it'd be right back over the limit a minute into the next file.
+        while thermal::cpu_temperature().map(| t | t > max_temp - TEMP_HYSTERESIS).unwrap_or(false) {
+            std::thread::sleep(Duration::from_secs(THERMAL_POLL_INTERVAL));
+        }
+
+        info!("CPU is at {:.0}°C, resuming.", thermal::cpu_temperature().unwrap_or_default());
+    }
+
+    /// Removes the intermediate file that was being written when a batch died.
+    /// Only that one, anything else there (finished outputs, segments) is still good.
+    fn clean_up_current_target(&mut self) {
+        if let Some(target) = self.current_target.take().filter(| target | target.exists()) {
+            if let Err(e) = fs::remove_file(&target) {
+                warn!("Failed to remove intermediate file {}: {e}", target.to_string_lossy());
+            }
+        }
+    }
+
+    fn transcodes_audio(&self, stream: &Stream) -> bool {
+        let lossless = analyze::LOSSLESS_AUDIO_CODECS.contains(&stream.codec());
+
+        match self.audio_mode {
+            _ if lossless && self.keep_lossless => false,
+            TranscodeMode::Auto => lossless,
+            TranscodeMode::Force => true,
+            TranscodeMode::Never => false
+        }
+    }
+
+    /// Polls the input directories forever, crunching new files once they're done being written.
+    /// `known` are the files that were already there for the first batch. Failed batches are logged, and watching goes on.
+    fn watch(&mut self, cfg: &args::AppArgs, mut known: HashSet<PathBuf>) {
+        // New files and their size on the last poll.
+        let mut pending: HashMap<PathBuf, u64> = HashMap::new();
+
+        // Whatever we write ourselves would get picked up as new, unless it lands in one of the watched dirs.
+        let input_dirs: Vec<PathBuf> = cfg.input_dirs().iter().map(| dir | absolute_path(dir)).collect();
+        let own_dirs: Vec<PathBuf> = [Some(cfg.output_dir()), cfg.intermediate_dir(), cfg.temp_dir()]
+            .into_iter()
+            .flatten()
+            .map(| dir | absolute_path(&dir))
+            .filter(| dir | !input_dirs.iter().any(| input | input.starts_with(dir)))
+            .collect()
+        ;
+
+        info!("Watching for new files...\n");
+
+        // Polling instead of filesystem events: notify isn't a dependency, and events don't make it across network mounts anyway.
+        loop {
+            std::thread::sleep(Duration::from_secs(WATCH_INTERVAL));
+
+            let mut ready = Vec::new();
+
+            for file in cfg.input_dirs().iter().flat_map(| dir | scan_dir(cfg, dir)) {
+                if known.contains(&file) || disk::is_partial_download(&file) || is_own_file(&file) {
+                    continue;
+                }
+
+                if own_dirs.iter().any(| dir | absolute_path(&file).starts_with(dir)) {
+                    continue;
+                }
+
+                let size = fs::metadata(&file).map(| m | m.len()).unwrap_or_default();
+
+                // Downloads and copies keep growing for a while, only a file that stayed the same across polls is done.
+                if pending.insert(file.clone(), size) == Some(size) && size > 0 {
+                    ready.push(file);
+                }
+            }
+
+            pending.retain(| file, _ | file.exists());
+
+            if ready.is_empty() {
+                continue;
+            }
+
+            for file in ready.iter() {
+                pending.remove(file);
+                known.insert(file.clone());
+            }
+
+            info!("Found {} new file(s).\n", ready.len());
+
+            self.files = ready;
+
+            if let Err(e) = self.start_cruncher() {
+                error!("Batch failed: {e}\n");
+                self.clean_up_current_target();
+            }
+
+            info!("Watching for new files...\n");
         }
     }
 
     fn start_cruncher(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let total_timer = Instant::now();
 
-        for file in self.files.iter() {
+        // Past the confirmation now, a new manifest can go. Dry runs and sweeps don't produce anything to record.
+        if !self.dry_run && self.crf_sweep.is_empty() {
+            self.manifest.begin()?;
+        }
+
+        let mut total_bytes: u64 = self.files.iter()
+            .filter_map(| file | fs::metadata(file).ok())
+            .map(| metadata | metadata.len())
+            .sum()
+        ;
+        let mut processed_bytes = 0;
+        let mut reports = Vec::new();
+        let mut file_times = Vec::new();
+        // Source and estimated output sizes for --dry-run.
+        let mut estimated_bytes = (0, 0);
+
+        let mut probes = std::mem::take(&mut self.probes);
+        let mut failed_assertions = 0;
+
+        for (n, file) in self.files.iter().enumerate() {
+            // Checked before cooling down, no point in waiting for a file that won't be processed.
+            if self.fail_fast && reports.last().map(| r: &FileReport | r.status == FileStatus::Failed).unwrap_or(false) {
+                error!("Stopping the batch after a failure, --fail-fast is on.\n");
+                break;
+            }
+
+            if n > 0 && !self.dry_run {
+                self.cool_down();
+            }
+
+            let file_timer = Instant::now();
+            // Anything that'd make --strict fail the file.
+            let mut file_warnings = 0;
             let file_name = file.file_name().unwrap().to_str().unwrap_or_default();
+            let relative_path = relative_output_path(&self.inputs, file, self.flatten);
+            let report_name = relative_path.to_string_lossy().to_string();
 
             info!("Processing file '{file_name}'");
 
-            let mkv = ffprobe::probe_file(file)?;
+            if !self.should_overwrite(file, &self.output.join(&relative_path)) {
+                info!("  Output already exists, skipping.\n");
+
+                let file_size = fs::metadata(file).map(| m | m.len()).unwrap_or_default();
+                reports.push(FileReport::unprocessed(report_name, FileStatus::Skipped, file_size, 0));
+
+                total_bytes = total_bytes.saturating_sub(file_size);
+                continue;
+            }
+
+            // A single broken file shouldn't take the rest of the batch down with it.
+            let probe = probes.remove(file).unwrap_or_else(|| {
+                if self.crf_sweep.is_empty() {
+                    ffprobe::probe_file(file, &self.probe_options)
+                }
+                else {
+                    ffprobe::probe_video(file, &self.probe_options)
+                }
+            });
+
+            let mut mkv = match probe {
+                Ok(mkv) => mkv,
+                Err(e) => {
+                    error!("  Failed to probe '{file_name}', skipping: {e}\n");
 
-            let transcode_video = match self.transcode_mode {
-                TranscodeMode::Auto => analyze_video(&mkv),
-                TranscodeMode::Force => true,
-                TranscodeMode::Never => false
+                    let file_size = fs::metadata(file).map(| m | m.len()).unwrap_or_default();
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, file_size, file_timer.elapsed().as_secs()));
+
+                    total_bytes = total_bytes.saturating_sub(file_size);
+                    continue;
+                }
             };
 
-            let kept_subs = analyze_sub_tracks(&mkv);
-            let kept_audio = analyze_audio_tracks(&mkv);
-            let kept_attachments = analyze_attachments(&mkv);
+            if self.infer_lang_from_filename {
+                if let Some(language) = analyze::language_from_file_name(file_name) {
+                    let changed = mkv.fill_missing_languages(Some(language), Some(language));
+
+                    if changed > 0 {
+                        info!("  Tagging {changed} untagged tracks as '{language}', going by the file name.");
+                    }
+                }
+            }
+
+            if let Some(min_duration) = self.min_duration {
+                if mkv.duration() < min_duration {
+                    info!("  File is shorter than {min_duration}s, skipping.\n");
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Skipped, mkv.size(), 0));
+
+                    total_bytes = total_bytes.saturating_sub(mkv.size());
+                    continue;
+                }
+            }
+
+            if self.precheck {
+                match integrity::decode_errors(file) {
+                    Ok(errors) if !errors.is_empty() => {
+                        error!("  Precheck found {} decode errors, skipping:", errors.len());
+
+                        for line in errors.iter().take(PRECHECK_LOGGED_ERRORS) {
+                            error!("    {line}");
+                        }
+
+                        println!();
+
+                        reports.push(FileReport {
+                            decode_errors: errors.len(),
+                            ..FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs())
+                        });
+
+                        total_bytes = total_bytes.saturating_sub(mkv.size());
+                        continue;
+                    }
+                    Ok(_) => info!("  Precheck passed."),
+                    Err(e) => warn!("  Failed to run the precheck on '{file_name}': {e}")
+                }
+            }
+
+            let primary_video = mkv.primary_video_stream();
+            let title = self.set_title_from_filename.then(|| title_from_file_name(file));
+
+            let metadata_sidecar = self.metadata_from.as_deref()
+                .map(| pattern | external::metadata_sidecar_path(file, pattern))
+                .filter(| path | path.is_file())
+            ;
+
+            let extra_metadata = match metadata_sidecar.as_ref().map(| path | (path, external::read_metadata_sidecar(path))) {
+                Some((path, Ok(metadata))) => {
+                    info!("  Adding {} metadata entries from '{}'.", metadata.len(), path.to_string_lossy());
+                    metadata
+                }
+                Some((path, Err(e))) => {
+                    warn!("  Failed to read metadata from '{}': {e}", path.to_string_lossy());
+                    file_warnings += 1;
+                    Vec::new()
+                }
+                None => Vec::new()
+            };
+
+            // Sweeps and metadata edits write files too, a dry run only gets to hear about them.
+            if self.dry_run && (!self.crf_sweep.is_empty() || self.rename_only) {
+                if !self.crf_sweep.is_empty() {
+                    info!("  Would run a CRF sweep on a {}s sample.\n", self.sample_length);
+                }
+                else {
+                    info!("  Would clean up the metadata, without touching the streams.\n");
+                }
+
+                continue;
+            }
+
+            if !self.crf_sweep.is_empty() {
+                if let Some((video_idx, video)) = primary_video {
+                    let pix_fmt = encoder::pixel_format(output_bit_depth(video.bit_depth(), self.output_bit_depth));
+                    let scratch_dir = self.temp_dir.as_ref().or(self.intermediate.as_ref()).unwrap_or(&self.output);
+
+                    sweep::crf_sweep(file, &mkv, video_idx, &self.encoder, pix_fmt, &self.crf_sweep, self.sample_length, scratch_dir, self.svt_log_level.as_deref(), self.nice);
+                    println!();
+                }
+                else {
+                    warn!("  File has no video, skipping CRF sweep.\n");
+                }
+
+                continue;
+            }
+
+            if self.rename_only {
+                let output_path = self.output.join(&relative_path);
+
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                match rename::rename_only(file, &mkv, &output_path, &self.keep_metadata, title.as_deref(), &extra_metadata) {
+                    Ok(()) => {
+                        info!("  Metadata cleaned up.\n");
+
+                        if let Err(e) = self.manifest.record(&relative_path.to_string_lossy()) {
+                            warn!("  Failed to record '{file_name}' on the manifest: {e}");
+                        }
+
+                        reports.push(FileReport {
+                            output_size: fs::metadata(&output_path).map(| m | m.len()).unwrap_or_default(),
+                            duration: mkv.duration(),
+                            ..FileReport::unprocessed(report_name, FileStatus::Copied, mkv.size(), file_timer.elapsed().as_secs())
+                        });
+                    }
+                    Err(e) => {
+                        warn!("  Failed to clean up metadata: {e}\n");
+                        reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+                    }
+                }
+
+                processed_bytes += mkv.size();
+                continue;
+            }
+
+            let plan = analyze::needs_crunching(&mkv, &self.filters, self.transcode_mode);
+            let drops_tracks = plan.drops_tracks(&mkv);
+
+            let CrunchPlan { transcode_video, subs: mut kept_subs, audio: mut kept_audio, attachments: kept_attachments, others: kept_others } = plan;
+
+            let burned_sub = self.burn_subs
+                .filter(| _ | primary_video.is_some())
+                .and_then(| setting | analyze::pick_burned_sub(&mkv, setting, &kept_subs))
+            ;
+
+            // Burned subs are part of the picture, no point in keeping the track too.
+            if let Some((burned_idx, burned)) = burned_sub {
+                info!(
+                    "  Burning subtitle track {burned_idx} ('{}', {}) into the video.",
+                    burned.stream_title(),
+                    burned.stream_language()
+                );
+
+                kept_subs.retain(| (idx, _) | *idx != burned_idx);
+            }
+
+            // Sources already at the requested rate are left alone. No rate to go by means converting, just in case.
+            let retimed = self.fps.as_ref().filter(| target | {
+                primary_video
+                    .map(| (_, s) | s.frame_rate().map(| rate | (rate - target.fps()).abs() > FPS_TOLERANCE).unwrap_or(true))
+                    .unwrap_or(false)
+            });
+
+            let transcode_video = transcode_video || burned_sub.is_some() || retimed.is_some();
+
+            // Burned subs would go out of sync on every segment after the first.
+            let segmented = self.segmented
+                .filter(| _ | transcode_video && burned_sub.is_none())
+                .and_then(| count | primary_video.map(| (idx, _) | (idx, count)))
+                .map(| (idx, count) | {
+                    let scratch_dir = self.temp_dir.as_ref().or(self.intermediate.as_ref()).unwrap_or(&self.output);
+                    (idx, SegmentedEncode::new(scratch_dir, file, count))
+                })
+            ;
+
+            let external_subs = self.external_subs.as_ref()
+                .map(| dir | external::find_external_subs(dir, file))
+                .unwrap_or_default()
+            ;
+
+            // Sidecars go after the external subs on ffmpeg's inputs.
+            let sidecar_input = 1 + external_subs.len();
+
+            let sidecars: Vec<(PathBuf, MkvFile)> = {
+                if self.sidecars {
+                    external::find_sidecars(file)
+                        .into_iter()
+                        .filter_map(| (path, language) | {
+                            match ffprobe::probe_file(&path, &self.probe_options) {
+                                Ok(mut sidecar) => {
+                                    if let Some(language) = language {
+                                        sidecar.fill_missing_languages(Some(language), Some(language));
+                                    }
+
+                                    Some((path, sidecar))
+                                }
+                                Err(e) => {
+                                    warn!("  Failed to probe sidecar '{}', leaving it out: {e}", path.to_string_lossy());
+                                    file_warnings += 1;
+                                    None
+                                }
+                            }
+                        })
+                        .collect()
+                }
+                else {
+                    Vec::new()
+                }
+            };
+
+            // The input each kept track comes from, 0 being the MKV itself.
+            let mut audio_inputs = vec![0; kept_audio.len()];
+            let mut sub_inputs = vec![0; kept_subs.len()];
+
+            for (n, (path, sidecar)) in sidecars.iter().enumerate() {
+                info!("  Adding sidecar '{}'.", path.to_string_lossy());
+
+                let sidecar_audio = analyze::analyze_audio_tracks(sidecar, &self.filters);
+                let sidecar_subs = analyze::analyze_sub_tracks(sidecar, &self.filters);
+
+                audio_inputs.extend(std::iter::repeat_n(sidecar_input + n, sidecar_audio.len()));
+                sub_inputs.extend(std::iter::repeat_n(sidecar_input + n, sidecar_subs.len()));
+
+                kept_audio.extend(sidecar_audio);
+                kept_subs.extend(sidecar_subs);
+            }
+
+            // The analysis hands tracks back in whatever order its filters left them in.
+            let mut audio: Vec<(usize, (usize, &Stream))> = audio_inputs.into_iter().zip(kept_audio).collect();
+            let mut subs: Vec<(usize, (usize, &Stream))> = sub_inputs.into_iter().zip(kept_subs).collect();
+
+            audio.sort_by_key(| (input, (_, s)) | (*input, s.index()));
+            subs.sort_by_key(| (input, (_, s)) | (*input, s.index()));
+
+            if let TrackOrder::PreferredLangFirst = self.track_order {
+                audio.sort_by_key(| (_, (_, s)) | s.stream_language() != "jpn");
+                subs.sort_by_key(| (_, (_, s)) | s.stream_language() != self.priority_sub_lang);
+            }
+
+            let (audio_inputs, kept_audio): (Vec<usize>, Vec<(usize, &Stream)>) = audio.into_iter().unzip();
+            let (sub_inputs, kept_subs): (Vec<usize>, Vec<(usize, &Stream)>) = subs.into_iter().unzip();
+
+            let (audio_tracks, sub_tracks, attachments) = (kept_audio.len(), kept_subs.len(), kept_attachments.len());
+
+            // Only the kept tracks get the default languages, tagging them before the analysis would have the filters drop them.
+            let untagged_audio = self.default_audio_lang.as_ref().map(| _ | analyze::untagged_tracks(&kept_audio)).unwrap_or_default();
+            let untagged_subs = self.default_sub_lang.as_ref().map(| _ | analyze::untagged_tracks(&kept_subs)).unwrap_or_default();
+
+            if !untagged_audio.is_empty() || !untagged_subs.is_empty() {
+                info!("  Tagging {} untagged tracks with the default languages.", untagged_audio.len() + untagged_subs.len());
+            }
+
+            // Checked on what the output would have, including external subs and fonts.
+            let broken_assertions: Vec<&TrackAssertion> = self.assertions.iter()
+                .filter(| assertion | {
+                    let filter = assertion.filter();
+
+                    let count = match assertion.kind() {
+                        TrackKind::Video => analyze::count_matching(primary_video.as_slice(), filter),
+                        TrackKind::Audio => analyze::count_matching(&kept_audio, filter),
+                        TrackKind::Subs => {
+                            analyze::count_matching(&kept_subs, filter)
+                                + external_subs.iter()
+                                    .filter(| sub | filter.map(| f | sub.language() == f || sub.codec().eq_ignore_ascii_case(f)).unwrap_or(true))
+                                    .count()
+                        }
+                        TrackKind::Attachments => {
+                            analyze::count_matching(&kept_attachments, filter)
+                                + self.external_fonts.iter()
+                                    .filter(| font | filter.map(| f | external::font_codec(font).eq_ignore_ascii_case(f)).unwrap_or(true))
+                                    .count()
+                        }
+                    };
+
+                    !assertion.holds(count)
+                })
+                .collect()
+            ;
+
+            if !broken_assertions.is_empty() {
+                for assertion in broken_assertions {
+                    error!("  Output would fail '{}'.", assertion.expression());
+                }
+
+                error!("  Failing file, it doesn't match the expected structure.\n");
+                reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+
+                failed_assertions += 1;
+                total_bytes = total_bytes.saturating_sub(mkv.size());
+                continue;
+            }
+
+            // Grouping (or preferring a language) only matters if it moves a track around.
+            let reorders_tracks = match self.track_order {
+                TrackOrder::Original => false,
+                TrackOrder::Grouped | TrackOrder::PreferredLangFirst => {
+                    let planned: Vec<usize> = primary_video.iter()
+                        .chain(kept_audio.iter())
+                        .chain(kept_subs.iter())
+                        .map(| (_, s) | s.index())
+                        .collect()
+                    ;
+
+                    planned.windows(2).any(| pair | pair[0] > pair[1])
+                }
+            };
+
+            // A remux that keeps everything and strips nothing would only bump the mux date.
+            let no_changes = !transcode_video
+                && mkv.video_streams().len() <= 1
+                && !drops_tracks
+                && !kept_audio.iter().any(| (_, s) | self.transcodes_audio(s))
+                // With no default track flagged, the first one would get flagged below.
+                && (kept_audio.len() <= 1 || kept_audio.iter().any(| (_, s) | s.is_default()))
+                && self.external_fonts.is_empty()
+                && external_subs.is_empty()
+                && sidecars.is_empty()
+                && !needs_metadata_changes(&mkv, &self.keep_metadata, title.as_deref())
+                && extra_metadata.is_empty()
+                && !kept_audio.iter().chain(kept_subs.iter()).any(| (_, s) | s.is_language_filled())
+                && untagged_audio.is_empty()
+                && untagged_subs.is_empty()
+                && !reorders_tracks
+            ;
+
+            if self.dry_run {
+                let estimated_size = {
+                    if transcode_video {
+                        let source_codec = primary_video.map(| (_, s) | s.codec()).unwrap_or_default();
+                        self.encoder.estimate_output_size(mkv.size(), source_codec)
+                    }
+                    else {
+                        mkv.size()
+                    }
+                };
+
+                info!(
+                    "  Would {} the video, estimated output ~{} (from {}).\n",
+                    if transcode_video { "transcode" } else { "copy" },
+                    ByteSize::b(estimated_size),
+                    ByteSize::b(mkv.size())
+                );
+
+                estimated_bytes.0 += mkv.size();
+                estimated_bytes.1 += estimated_size;
+                continue;
+            }
+
+            if no_changes {
+                if self.strict && file_warnings > 0 {
+                    error!("  Failing file in strict mode, {file_warnings} warning(s) before copying.\n");
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+
+                    total_bytes = total_bytes.saturating_sub(mkv.size());
+                    continue;
+                }
+
+                if !self.has_space_for(mkv.size(), None) {
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Skipped, mkv.size(), 0));
+
+                    total_bytes = total_bytes.saturating_sub(mkv.size());
+                    continue;
+                }
+
+                info!("  No changes needed, copying the file as-is.\n");
+
+                let output_path = self.output.join(&relative_path);
+
+                let copied = match self.stdout_output.as_ref() {
+                    Some(stdout_output) => {
+                        fs::File::open(file).and_then(| mut source | io::copy(&mut source, &mut stdout_output.try_clone()?))
+                    }
+                    None => {
+                        output_path.parent().map(fs::create_dir_all).unwrap_or(Ok(())).and_then(| _ | {
+                            // Copying a file onto itself truncates it first.
+                            if same_file(file, &output_path) {
+                                Ok(mkv.size())
+                            }
+                            else {
+                                fs::copy(file, &output_path)
+                            }
+                        })
+                    }
+                };
+
+                if let Err(e) = copied {
+                    error!("  Failed to copy '{file_name}': {e}\n");
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+
+                    total_bytes = total_bytes.saturating_sub(mkv.size());
+                    continue;
+                }
+
+                let output_hash = self.stdout_output.is_none().then(|| self.finish_output(file, &output_path, false)).flatten();
+
+                if let Err(e) = self.manifest.record(&relative_path.to_string_lossy()) {
+                    warn!("  Failed to record '{file_name}' on the manifest: {e}");
+                }
+
+                reports.push(FileReport {
+                    file_name: report_name,
+                    status: FileStatus::Copied,
+                    input_size: mkv.size(),
+                    output_size: mkv.size(),
+                    duration: mkv.duration(),
+                    transcoded: false,
+                    audio_tracks,
+                    sub_tracks,
+                    attachments,
+                    decode_errors: 0,
+                    hash: output_hash,
+                    quality_score: None,
+                    elapsed_secs: file_timer.elapsed().as_secs()
+                });
+
+                processed_bytes += mkv.size();
+                continue;
+            }
+
+            // Copied files are quick to write, going through the intermediate dir is pure overhead for them.
+            let intermediate = self.intermediate.as_ref().filter(| _ | transcode_video || !self.intermediate_only_transcodes);
+
+            // Better to skip the file now than have ffmpeg run out of space halfway through it.
+            if !self.has_space_for(mkv.size(), intermediate) {
+                reports.push(FileReport::unprocessed(report_name, FileStatus::Skipped, mkv.size(), 0));
+
+                total_bytes = total_bytes.saturating_sub(mkv.size());
+                continue;
+            }
 
             let mut ffmpeg_arguments = vec![
                 // Silence ffmpeg.
                 String::from("-hide_banner"), String::from("-loglevel"), String::from("error"),
-                // Print progress stats to stdout, always overwrite existing files.
-                String::from("-progress"), String::from("pipe:1"), String::from("-y"),
+                // Print progress stats to stdout (stderr if that's where the output goes),
+                // always overwrite existing files (--overwrite was already checked).
+                String::from("-progress"), String::from(if self.stdout_output.is_some() { "pipe:2" } else { "pipe:1" }), String::from("-y"),
             ];
 
+            if self.copy_timestamps {
+                ffmpeg_arguments.push(String::from("-copyts"));
+            }
+
+            // ffmpeg needs to see the same streams ffprobe saw.
+            ffmpeg_arguments.extend(self.probe_options.arguments());
+
+            // Decoding speed only matters when there's something to encode.
+            if let Some(hwaccel) = self.hwaccel.filter(| _ | transcode_video) {
+                ffmpeg_arguments.push(String::from("-hwaccel"));
+                ffmpeg_arguments.push(String::from(hwaccel.as_str()));
+            }
+
             let mut file_buffer = Vec::new();
 
             let preload_fn = | ffmpeg_arguments: &mut Vec<String> | -> Vec<u8> {
@@ -127,15 +1226,29 @@ impl Cruncher {
 
             let no_preload_fn = | ffmpeg_arguments: &mut Vec<String> | {
                 ffmpeg_arguments.push(String::from("-i"));
-                ffmpeg_arguments.push(file.to_str().unwrap_or_default().to_owned());
+                ffmpeg_arguments.push(absolute_path(file).to_string_lossy().to_string());
             };
 
             // Avoid locking up my system by loading massive files.
             // Also, don't load files into memory if we are not transcoding video,
             // it usually ends up taking longer to load it up than to crunch the file.
             match self.preload_mode {
+                // The subtitles filter opens the file on its own, it needs a real path.
+                _ if burned_sub.is_some() => {
+                    info!("  Burning in subtitles, disabling preload...");
+                    no_preload_fn(&mut ffmpeg_arguments);
+                }
+                // The segments read the file on their own, the output only needs its audio and subs.
+                _ if segmented.is_some() => {
+                    info!("  Encoding in segments, disabling preload...");
+                    no_preload_fn(&mut ffmpeg_arguments);
+                }
                 PreloadMode::Auto => {
-                    if transcode_video {
+                    if self.remote_input {
+                        info!("  Input is on a network mount, disabling preload...");
+                        no_preload_fn(&mut ffmpeg_arguments);
+                    }
+                    else if transcode_video {
                         if ByteSize::b(mkv.size()) < ByteSize::gib(3) {
                             info!("  Loading MKV file into memory.");
                             file_buffer = preload_fn(&mut ffmpeg_arguments);
@@ -160,67 +1273,302 @@ impl Cruncher {
                 }
             }
 
-            // Grab only the first video stream. Skips cover pictures and horrible fuck-ups.
-            ffmpeg_arguments.push(String::from("-map"));
-            ffmpeg_arguments.push(String::from("0:v:0"));
+            // External inputs go after the MKV, so it stays as input 0.
+            for sub in external_subs.iter() {
+                info!("  Adding external subtitles '{}' ({}).", sub.path().to_string_lossy(), sub.language());
 
-            // Use -map 0:s if all subs are being kept instead of mapping one by one.
-            // The is_empty check is a failsafe to avoid mapping when there are *no* subtitles.
-            // IIRC, ffmpeg doesn't like that, so don't remove it, future me.
-            if !kept_subs.is_empty() && kept_subs.len() == mkv.subtitles_streams().len() {
-                ffmpeg_arguments.push(String::from("-map"));
-                ffmpeg_arguments.push(String::from("0:s"));
+                ffmpeg_arguments.push(String::from("-i"));
+                ffmpeg_arguments.push(absolute_path(sub.path()).to_string_lossy().to_string());
             }
-            else {
-                for (stream_idx, _) in kept_subs {
-                    ffmpeg_arguments.push(String::from("-map"));
-                    ffmpeg_arguments.push(format!("0:s:{stream_idx}"));
+
+            for (path, _) in sidecars.iter() {
+                ffmpeg_arguments.push(String::from("-i"));
+                ffmpeg_arguments.push(absolute_path(path).to_string_lossy().to_string());
+            }
+
+            // The encoded segments come in last, the list only has to exist by the time ffmpeg starts.
+            let segments_input = sidecar_input + sidecars.len();
+
+            if let Some((_, segments)) = segmented.as_ref() {
+                ffmpeg_arguments.extend(["-f", "concat", "-safe", "0", "-i"].map(String::from));
+                ffmpeg_arguments.push(segments.concat_list().to_string_lossy().to_string());
+            }
+
+            // Every mapped track, along with where it sat on the inputs and its group on the output.
+            let mut maps = Vec::new();
+
+            // Grab only the first real video stream. Skips cover pictures and horrible fuck-ups.
+            if let Some((video_idx, video)) = primary_video {
+                let video_streams = mkv.video_streams();
+
+                if video_streams.len() > 1 {
+                    info!("  Using video stream {video_idx} (#{}) out of {}.", video_streams[video_idx].index(), video_streams.len());
                 }
+
+                let map = {
+                    if segmented.is_some() {
+                        format!("{segments_input}:v:0")
+                    }
+                    else {
+                        format!("0:v:{video_idx}")
+                    }
+                };
+
+                maps.push(((0, video.index()), 0, map));
+            }
+
+            for (input, (stream_idx, stream)) in audio_inputs.iter().zip(kept_audio.iter()) {
+                maps.push(((*input, stream.index()), 1, format!("{input}:a:{stream_idx}")));
+            }
+
+            for (input, (stream_idx, stream)) in sub_inputs.iter().zip(kept_subs.iter()) {
+                maps.push(((*input, stream.index()), 2, format!("{input}:s:{stream_idx}")));
+            }
+
+            // ffmpeg lays the output out in map order. Either way, tracks of the same type keep the order they have above,
+            // which the per-stream options below go by.
+            match self.track_order {
+                TrackOrder::Original => maps.sort_by_key(| (position, _, _) | *position),
+                TrackOrder::Grouped | TrackOrder::PreferredLangFirst => maps.sort_by_key(| (_, group, _) | *group)
             }
 
-            for (stream_idx, stream) in kept_audio.iter() {
+            for (_, _, map) in maps {
                 ffmpeg_arguments.push(String::from("-map"));
-                ffmpeg_arguments.push(format!("0:a:{stream_idx}"));
+                ffmpeg_arguments.push(map);
+            }
 
-                if LOSSLESS_AUDIO_CODECS.contains(&stream.codec()) {
-                    ffmpeg_arguments.push(String::from("-c:a"));
+            // Audio gets its own decision, independent of whatever happens to the video.
+            // Codec options go per output stream, a plain -c:a would apply the last one to every track.
+            for (output_idx, (input, (stream_idx, stream))) in audio_inputs.iter().zip(kept_audio.iter()).enumerate() {
+                if self.transcodes_audio(stream) {
+                    ffmpeg_arguments.push(format!("-c:a:{output_idx}"));
                     ffmpeg_arguments.push(String::from("libopus"));
-                    ffmpeg_arguments.push(String::from("-ac"));
-                    ffmpeg_arguments.push(String::from("2"));
+                    ffmpeg_arguments.push(format!("-ac:a:{output_idx}"));
+                    ffmpeg_arguments.push(OUTPUT_AUDIO_CHANNELS.to_string());
+
+                    let mut filters: Vec<String> = encoder::downmix_filter(self.downmix, stream.channels()).into_iter().collect();
+
+                    if let Some(target) = self.loudnorm {
+                        // -ac only downmixes after the filters, loudnorm has to hear the stereo mix that ends up in the output.
+                        if filters.is_empty() {
+                            filters.push(String::from("aformat=channel_layouts=stereo"));
+                        }
+
+                        let measurement = {
+                            if self.loudnorm_2pass {
+                                info!("  Measuring the loudness of audio track {stream_idx}...");
+
+                                let source = {
+                                    if *input == 0 {
+                                        file.as_path()
+                                    }
+                                    else {
+                                        sidecars[input - sidecar_input].0.as_path()
+                                    }
+                                };
+
+                                let measurement = loudness::measure_loudness(source, *stream_idx, &filters, OUTPUT_AUDIO_CHANNELS, target);
+
+                                if measurement.is_none() {
+                                    warn!("  Failed to measure the loudness of audio track {stream_idx}, normalizing in a single pass.");
+                                }
+
+                                measurement
+                            }
+                            else {
+                                None
+                            }
+                        };
+
+                        filters.push(loudness::loudnorm_filter(target, measurement.as_ref()));
+                    }
+
+                    if !filters.is_empty() {
+                        ffmpeg_arguments.push(format!("-filter:a:{output_idx}"));
+                        ffmpeg_arguments.push(filters.join(","));
+                    }
+
+                    if let Some(bitrate) = self.opus_bitrate.as_ref() {
+                        ffmpeg_arguments.push(format!("-b:a:{output_idx}"));
+                        ffmpeg_arguments.push(encoder::opus_bitrate(bitrate));
+                    }
                 }
                 else {
-                    ffmpeg_arguments.push(String::from("-c:a"));
+                    ffmpeg_arguments.push(format!("-c:a:{output_idx}"));
                     ffmpeg_arguments.push(String::from("copy"));
                 }
             }
 
+            // Keep the default track the source wanted, or fall back to the first japanese one.
+            let default_audio = kept_audio.iter()
+                .position(| (_, s) | s.is_default())
+                .or_else(|| kept_audio.iter().position(| (_, s) | s.stream_language() == "jpn"))
+                .unwrap_or_default()
+            ;
+
+            if kept_audio.len() > 1 {
+                for idx in 0..kept_audio.len() {
+                    ffmpeg_arguments.push(format!("-disposition:a:{idx}"));
+
+                    if idx == default_audio {
+                        ffmpeg_arguments.push(String::from("default"));
+                    }
+                    else {
+                        ffmpeg_arguments.push(String::from("0"));
+                    }
+                }
+            }
+
+            // Languages filled in on our side only exist on our copy of the probe until they're written out.
+            for (kind, kept) in [("a", &kept_audio), ("s", &kept_subs)] {
+                for (idx, (_, stream)) in kept.iter().enumerate().filter(| (_, (_, s)) | s.is_language_filled()) {
+                    ffmpeg_arguments.push(format!("-metadata:s:{kind}:{idx}"));
+                    ffmpeg_arguments.push(format!("language={}", stream.stream_language()));
+                }
+            }
+
+            for (kind, untagged, language) in [("a", &untagged_audio, &self.default_audio_lang), ("s", &untagged_subs, &self.default_sub_lang)] {
+                if let Some(language) = language {
+                    for idx in untagged {
+                        ffmpeg_arguments.push(format!("-metadata:s:{kind}:{idx}"));
+                        ffmpeg_arguments.push(format!("language={language}"));
+                    }
+                }
+            }
+
             // Same deal as subs mapping, no removing the is_empty check. It's important.
             if !kept_attachments.is_empty() && kept_attachments.len() == mkv.attachments().len() {
                 ffmpeg_arguments.push(String::from("-map"));
                 ffmpeg_arguments.push(String::from("0:t"));
             }
             else {
-                for (attachment, _) in kept_attachments {
+                for (attachment, _) in kept_attachments.iter() {
                     ffmpeg_arguments.push(String::from("-map"));
                     ffmpeg_arguments.push(format!("0:t:{attachment}"));
                 }
             }
-            
+
+            for (idx, sub) in external_subs.iter().enumerate() {
+                ffmpeg_arguments.push(String::from("-map"));
+                ffmpeg_arguments.push(format!("{}:s:0", idx + 1));
+
+                ffmpeg_arguments.push(format!("-metadata:s:s:{}", kept_subs.len() + idx));
+                ffmpeg_arguments.push(format!("language={}", sub.language()));
+            }
+
+            for (idx, font) in self.external_fonts.iter().enumerate() {
+                ffmpeg_arguments.push(String::from("-attach"));
+                ffmpeg_arguments.push(absolute_path(font).to_string_lossy().to_string());
+
+                ffmpeg_arguments.push(format!("-metadata:s:t:{}", kept_attachments.len() + idx));
+                ffmpeg_arguments.push(format!("mimetype={}", external::font_mime_type(font)));
+            }
+
+            // Unknown streams go by their absolute index, there's no stream specifier that covers all of them.
+            for stream in kept_others.iter() {
+                ffmpeg_arguments.push(String::from("-map"));
+                ffmpeg_arguments.push(format!("0:{}", stream.index()));
+            }
+
+            if !kept_others.is_empty() {
+                ffmpeg_arguments.push(String::from("-copy_unknown"));
+                ffmpeg_arguments.push(String::from("-c:d"));
+                ffmpeg_arguments.push(String::from("copy"));
+            }
+
+            // Segments get encoded on their own, the output only copies the video back out of them.
+            let mut video_arguments = Vec::new();
+
             if transcode_video {
-                ffmpeg_arguments.push(String::from("-c:v"));
-                ffmpeg_arguments.push(String::from("libsvtav1"));
+                video_arguments.extend(self.encoder.ffmpeg_arguments());
+
+                // Keyframes every 5 seconds, give or take.
+                let gop = retimed.map(| target | (target.fps() * GOP_SECONDS).round() as u32).unwrap_or(120);
+
+                video_arguments.push(String::from("-g"));
+                video_arguments.push(gop.to_string());
+
+                let source_depth = primary_video.map(| (_, s) | s.bit_depth()).unwrap_or(8);
+                let target_depth = output_bit_depth(source_depth, self.output_bit_depth);
+
+                if source_depth != target_depth {
+                    if source_depth > target_depth {
+                        warn!("  Video will be truncated from {source_depth}-bit to {target_depth}-bit.");
+                        file_warnings += 1;
+                    }
+                    else {
+                        info!("  Video will be converted from {source_depth}-bit to {target_depth}-bit.");
+                    }
+                }
+
+                video_arguments.push(String::from("-pix_fmt"));
+                video_arguments.push(String::from(encoder::pixel_format(target_depth)));
+
+                let mut video_filters = Vec::new();
+
+                // First in the chain, there's no point in filtering frames that get dropped anyway.
+                if let Some(target) = retimed {
+                    let source_rate = primary_video.and_then(| (_, s) | s.frame_rate());
+
+                    info!(
+                        "  Converting the video from {} to {:.3} fps, playback timing changes as frames get dropped or duplicated.",
+                        source_rate.map(| rate | format!("{rate:.3}")).unwrap_or_else(|| String::from("an unknown rate")),
+                        target.fps()
+                    );
+
+                    video_filters.push(format!("fps={}", target.as_str()));
+                    video_arguments.extend([String::from("-r"), target.as_str().to_owned()]);
+                }
+
+                if let Some((_, video)) = primary_video.filter(| (_, s) | s.is_hdr()) {
+                    if self.tonemap != Tonemap::Off {
+                        info!("  HDR source, tone-mapping to SDR ({}).", self.tonemap.as_str());
+
+                        video_filters.push(encoder::tonemap_filter(self.tonemap, encoder::pixel_format(target_depth)));
+                        video_arguments.extend(color_arguments("bt709", "bt709", "bt709"));
+                    }
+                    else if let Some(color) = video.color() {
+                        info!("  HDR source, keeping HDR.");
+
+                        // The encoder doesn't carry these over on its own, players would show it washed out.
+                        video_arguments.extend(color_arguments(&color.primaries, &color.transfer, &color.space));
+                    }
+                }
+
+                // Rendered after tone-mapping, so the subs don't get tone-mapped along with the picture.
+                if let Some((burned_idx, _)) = burned_sub {
+                    video_filters.push(encoder::subtitles_filter(&absolute_path(file), burned_idx));
+                }
+
+                if !video_filters.is_empty() {
+                    video_arguments.push(String::from("-vf"));
+                    video_arguments.push(video_filters.join(","));
+                }
 
-                ffmpeg_arguments.push(String::from("-crf"));
-                ffmpeg_arguments.push(String::from("30"));
+                let variable_frame_rate = primary_video.map(| (_, s) | s.is_variable_frame_rate()).unwrap_or(false);
 
-                ffmpeg_arguments.push(String::from("-preset"));
-                ffmpeg_arguments.push(String::from("7"));
+                if variable_frame_rate && retimed.is_none() {
+                    warn!("  Source looks like variable frame rate.");
+                    file_warnings += 1;
+                }
 
-                ffmpeg_arguments.push(String::from("-g"));
-                ffmpeg_arguments.push(String::from("120"));
+                // Left alone, ffmpeg might duplicate or drop frames to make VFR sources constant.
+                let fps_mode = match self.vfr {
+                    // The fps filter already made it constant.
+                    _ if retimed.is_some() => Some("cfr"),
+                    VfrMode::Auto => variable_frame_rate.then_some("passthrough"),
+                    VfrMode::Cfr => Some("cfr"),
+                    VfrMode::Passthrough => Some("passthrough")
+                };
+
+                if let Some(fps_mode) = fps_mode {
+                    video_arguments.push(String::from("-fps_mode"));
+                    video_arguments.push(String::from(fps_mode));
+                }
+            }
 
-                ffmpeg_arguments.push(String::from("-pix_fmt"));
-                ffmpeg_arguments.push(String::from("yuv420p10le"));
+            if transcode_video && segmented.is_none() {
+                ffmpeg_arguments.append(&mut video_arguments);
             }
             else {
                 ffmpeg_arguments.push(String::from("-c:v"));
@@ -231,25 +1579,15 @@ impl Cruncher {
             ffmpeg_arguments.push(String::from("-c:s"));
             ffmpeg_arguments.push(String::from("copy"));
 
-            // Remove title metadata from the file
-            ffmpeg_arguments.push(String::from("-metadata"));
-            ffmpeg_arguments.push(String::from("title="));
+            ffmpeg_arguments.extend(metadata_arguments(&mkv, &self.keep_metadata, title.as_deref(), &extra_metadata));
 
-            // and the video track
-            ffmpeg_arguments.push(String::from("-metadata:s:v"));
-            ffmpeg_arguments.push(String::from("title="));
-
-            // *and* the audio track.
-            ffmpeg_arguments.push(String::from("-metadata:s:a"));
-            ffmpeg_arguments.push(String::from("title="));
-
-            // Some people add language metadata to video streams for some reason.
-            // Don't be like those people, you throw off my shit scripts.
-            ffmpeg_arguments.push(String::from("-metadata:s:v"));
-            ffmpeg_arguments.push(String::from("language=und"));
+            // Otherwise the muxer stamps the date, its own version and random UIDs on every output.
+            if self.deterministic {
+                ffmpeg_arguments.extend(["-fflags", "+bitexact", "-flags:v", "+bitexact", "-flags:a", "+bitexact"].map(String::from));
+            }
 
             let mut target_path = {
-                if let Some(intermediate) = self.intermediate.as_ref() {
+                if let Some(intermediate) = intermediate {
                     intermediate.clone()
                 }
                 else {
@@ -257,29 +1595,102 @@ impl Cruncher {
                 }
             };
 
-            target_path.push(file_name);
-            ffmpeg_arguments.push(target_path.to_str().unwrap_or_default().to_owned());
+            target_path.push(&relative_path);
+
+            // ffmpeg writes here, and the output only takes its real name once it's finished.
+            // A failed encode never leaves a truncated file under the name the next run (or --overwrite) goes by.
+            let partial_path = partial_output_path(&target_path);
+
+            // There's no file name to guess the format from on a pipe.
+            if self.stdout_output.is_some() {
+                ffmpeg_arguments.extend([String::from("-f"), String::from("matroska"), String::from("pipe:1")]);
+            }
+            else {
+                if let Some(parent) = target_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                // The partial name has no extension to guess the format from.
+                ffmpeg_arguments.extend([String::from("-f"), String::from("matroska")]);
+                ffmpeg_arguments.push(absolute_path(&partial_path).to_string_lossy().to_string());
+            }
+
+            if self.strict && file_warnings > 0 {
+                error!("  Failing file in strict mode, {file_warnings} warning(s) before encoding.\n");
+                reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+
+                total_bytes = total_bytes.saturating_sub(mkv.size());
+                continue;
+            }
+
+            // Left behind by a run that crashed halfway through. The output itself went through --overwrite already.
+            if intermediate.is_some() && target_path.exists() && !same_file(&target_path, file) {
+                info!("  Removing a stale intermediate file from an earlier run.");
+
+                if let Err(e) = fs::remove_file(&target_path) {
+                    error!("  Failed to remove the stale intermediate file, skipping: {e}\n");
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+
+                    total_bytes = total_bytes.saturating_sub(mkv.size());
+                    continue;
+                }
+            }
+
+            if let Some((video_idx, segments)) = segmented.as_ref() {
+                let encoded = segments.encode(file, mkv.duration(), *video_idx, &video_arguments, self.svt_log_level.as_deref(), self.nice);
+
+                if let Err(e) = encoded {
+                    error!("  Failed to encode the segments, the finished ones are kept for the next run: {e}\n");
+                    reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+
+                    total_bytes = total_bytes.saturating_sub(mkv.size());
+                    continue;
+                }
+
+                info!("  All segments encoded, putting the output together.");
+            }
+
+            if self.stdout_output.is_none() {
+                self.current_target = Some(partial_path.clone());
+            }
 
             let mut ffmpeg_process = Command::new("ffmpeg");
 
+            // Anything ffmpeg writes on its own (pass logs and the like) lands on its working directory.
+            if let Some(temp_dir) = self.temp_dir.as_ref() {
+                ffmpeg_process.current_dir(temp_dir);
+            }
+
             if !file_buffer.is_empty() {
                 ffmpeg_process.stdin(std::process::Stdio::piped());
             }
 
-            ffmpeg_process
-                .args(ffmpeg_arguments)
-                .env("SVT_LOG", "fatal")
-                .stdout(std::process::Stdio::piped());
+            if let Some(level) = self.svt_log_level.as_ref() {
+                ffmpeg_process.env("SVT_LOG", level);
+            }
+
+            if let Some(niceness) = self.nice {
+                priority::set_niceness(&mut ffmpeg_process, niceness);
+            }
+
+            ffmpeg_process.args(ffmpeg_arguments);
+
+            // ffmpeg writes the output straight to the real stdout, progress comes through stderr instead.
+            if let Some(stdout_output) = self.stdout_output.as_ref() {
+                ffmpeg_process
+                    .stdout(stdout_output.try_clone()?)
+                    .stderr(std::process::Stdio::piped());
+            }
+            else {
+                ffmpeg_process.stdout(std::process::Stdio::piped());
+            }
+
+            // Every way out of the block below without a report means the file failed.
+            let report_count = reports.len();
 
             if let Ok(mut handle) = ffmpeg_process.spawn() {
                 // Moving the duration down from seconds to microseconds.
-                let bar = ProgressBar::new((mkv.duration() as u64 * 1000) * 1000);
-
-                bar.set_style(
-                    ProgressStyle::with_template("Processing... {percent}% {wide_bar} ({msg} - Elapsed: {elapsed_precise})")
-                    .unwrap()
-                    .progress_chars("##-")
-                );
+                let mut bar = FileProgress::new((mkv.duration() as u64 * 1000) * 1000, self.animated_progress, file_name);
 
                 if let Some(mut stdin) = handle.stdin.take() {
                     std::thread::spawn(move || {
@@ -287,52 +1698,316 @@ impl Cruncher {
                     });
                 }
 
-                if let Some(stdout) = handle.stdout.take() {
-                    let stdout_reader = BufReader::new(stdout);
-                    let stdout_lines = stdout_reader.lines();
+                let progress: Option<Box<dyn Read>> = match (handle.stdout.take(), handle.stderr.take()) {
+                    (Some(stdout), _) => Some(Box::new(stdout)),
+                    (None, Some(stderr)) => Some(Box::new(stderr)),
+                    (None, None) => None
+                };
+
+                if let Some(progress) = progress {
+                    let progress_reader = BufReader::new(progress);
+                    let progress_lines = progress_reader.lines();
 
-                    for line in stdout_lines.flatten() {
+                    for line in progress_lines.map_while(Result::ok) {
                         if let Some((key, value)) = line.split_once('=') {
                             match key {
-                                "speed" => bar.set_message(value.to_owned()),
+                                "fps" => bar.set_fps(value),
+                                "speed" => bar.set_speed(value),
                                 "out_time_ms" => bar.set_position(value.parse().unwrap_or_default()),
                                 _ => {}
                             }
                         }
+                        // ffmpeg's errors share stderr with the progress.
+                        else if !line.is_empty() {
+                            warn!("  ffmpeg: {line}");
+                        }
+                    }
+                }
+
+                let wait_result = handle.wait();
+
+                if let Some((_, segments)) = segmented.as_ref() {
+                    if wait_result.as_ref().map(| status | status.success()).unwrap_or(false) {
+                        segments.clean_up();
+                    }
+                }
+
+                // Nothing was written on our side to check or move around.
+                if self.stdout_output.is_some() {
+                    bar.finish();
+
+                    if wait_result.map(| status | status.success()).unwrap_or(false) {
+                        reports.push(FileReport {
+                            file_name: report_name.clone(),
+                            status: if transcode_video { FileStatus::Encoded } else { FileStatus::Copied },
+                            input_size: mkv.size(),
+                            output_size: 0,
+                            duration: mkv.duration(),
+                            transcoded: transcode_video,
+                            audio_tracks,
+                            sub_tracks,
+                            attachments,
+                            decode_errors: 0,
+                            hash: None,
+                            quality_score: None,
+                            elapsed_secs: file_timer.elapsed().as_secs()
+                        });
                     }
                 }
+                else if let Ok(status) = wait_result {
+                    let mut output_path = self.output.clone();
+                    output_path.push(&relative_path);
+
+                    // Decided on the partial output, an in-place run has no source left to keep once it's moved into place.
+                    let kept_source = status.success() && {
+                        let partial_size = fs::metadata(&partial_path).map(| m | m.len()).unwrap_or_default();
+                        self.keeps_source(mkv.size(), partial_size, transcode_video)
+                    };
+
+                    let finished = status.success() && {
+                        if kept_source {
+                            if !same_file(file, &output_path) {
+                                if let Some(parent) = output_path.parent() {
+                                    fs::create_dir_all(parent)?;
+                                }
+
+                                fs::copy(file, &output_path)?;
+                            }
+
+                            true
+                        }
+                        else {
+                            match fs::rename(&partial_path, &target_path) {
+                                Ok(()) => true,
+                                Err(e) => {
+                                    error!("  Failed to move the finished output into place: {e}");
+                                    false
+                                }
+                            }
+                        }
+                    };
+
+                    if !status.success() {
+                        warn!("  ffmpeg exited with {status}, removing the partial output.");
+                    }
+
+                    if (!finished || kept_source) && partial_path.exists() {
+                        if let Err(e) = fs::remove_file(&partial_path) {
+                            warn!("  Failed to remove the partial output: {e}");
+                        }
+                    }
+
+                    // Cleared when the copy out of the intermediate dir couldn't be checked, which fails the file.
+                    let mut copy_verified = true;
 
-                if handle.wait().is_ok() {
-                    if self.intermediate.is_some() {
-                        let mut output_path = self.output.clone();
-                        output_path.push(file_name);
+                    if intermediate.is_some() && finished && !kept_source {
+                        if let Some(parent) = output_path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
 
                         fs::copy(&target_path, &output_path).expect("Failed to copy processed file from intermediate dir");
 
-                        if transcode_video {
-                            let source_hash = seahash::hash(&fs::read(&target_path).unwrap_or_default());
-                            let target_hash = seahash::hash(&fs::read(&output_path).unwrap_or_default());
+                        // Reading both files back in full is slow over the network, the size is good enough there.
+                        if transcode_video && (self.remote_input || self.hash == HashAlgorithm::None) {
+                            let source_size = fs::metadata(&target_path).map(| m | m.len()).ok();
+                            let target_size = fs::metadata(&output_path).map(| m | m.len()).ok();
 
-                            if source_hash != target_hash {
-                                panic!("Hash mismatch on output file!");
+                            if source_size != target_size {
+                                panic!("Size mismatch on output file!");
+                            }
+                        }
+                        else if transcode_video {
+                            match (disk::hash_file(&target_path, self.hash), disk::hash_file(&output_path, self.hash)) {
+                                (Ok(source_hash), Ok(target_hash)) => {
+                                    if source_hash != target_hash {
+                                        panic!("Hash mismatch on output file!");
+                                    }
+                                }
+                                // Not a mismatch, the copy just can't be vouched for. The intermediate file is left alone.
+                                (Err(e), _) | (_, Err(e)) => {
+                                    error!("  Failed to hash the output to verify its copy, failing the file: {e}\n");
+
+                                    if let Err(e) = fs::remove_file(&output_path) {
+                                        warn!("  Failed to remove the unverified output: {e}");
+                                    }
+
+                                    copy_verified = false;
+                                }
                             }
                         }
 
-                        fs::remove_file(&target_path).expect("Failed to remove processed file from intermediate dir");
+                        if copy_verified {
+                            fs::remove_file(&target_path).expect("Failed to remove processed file from intermediate dir");
+                        }
                     }
 
                     bar.finish();
-                    println!("\n");
+
+                    if finished && copy_verified {
+                        // ffmpeg happily exits 0 after dropping a track it couldn't mux.
+                        if !kept_source {
+                            match ffprobe::probe_file(&output_path, &self.probe_options) {
+                                Ok(output_mkv) => {
+                                    let checks = [
+                                        ("audio", analyze::missing_tracks(&kept_audio, &output_mkv.audio_streams())),
+                                        ("subtitle", analyze::missing_tracks(&kept_subs, &output_mkv.subtitles_streams())),
+                                        ("attachment", analyze::missing_tracks(&kept_attachments, &output_mkv.attachments()))
+                                    ];
+
+                                    for (kind, missing) in checks {
+                                        if !missing.is_empty() {
+                                            warn!("  Output is missing {} {kind} track(s): {}", missing.len(), missing.join(", "));
+                                            file_warnings += 1;
+                                        }
+                                    }
+
+                                    if (output_mkv.duration() - mkv.duration()).abs() > DURATION_TOLERANCE {
+                                        warn!("  Output runs for {:.1}s, the source for {:.1}s.", output_mkv.duration(), mkv.duration());
+                                        file_warnings += 1;
+                                    }
+                                }
+                                Err(e) => {
+                                    warn!("  Failed to probe the output to check its tracks: {e}");
+                                    file_warnings += 1;
+                                }
+                            }
+                        }
+
+                        if self.strict && file_warnings > 0 {
+                            error!("  Failing file in strict mode after {file_warnings} warning(s), removing the output.\n");
+
+                            if let Err(e) = fs::remove_file(&output_path) {
+                                warn!("  Failed to remove the output: {e}");
+                            }
+                        }
+                        else {
+                            let mut quality_score = None;
+
+                            if let Some(metric) = self.measure_quality.filter(| _ | transcode_video && !kept_source) {
+                                info!("  Measuring output quality ({metric:?}), this might take a while...");
+
+                                let source_video_idx = primary_video.map(| (idx, _) | idx).unwrap_or_default();
+
+                                quality_score = quality::measure_quality(metric, &output_path, file, source_video_idx, self.temp_dir.as_deref());
+
+                                match quality_score {
+                                    Some(score) => info!("  {metric:?} score: {score:.4}\n"),
+                                    None => warn!("  Failed to measure output quality.\n")
+                                }
+                            }
+
+                            let output_hash = self.finish_output(file, &output_path, kept_source);
+
+                            if let Err(e) = self.manifest.record(&relative_path.to_string_lossy()) {
+                                warn!("  Failed to record '{file_name}' on the manifest: {e}");
+                            }
+
+                            let status = {
+                                if transcode_video && !kept_source {
+                                    FileStatus::Encoded
+                                }
+                                else {
+                                    FileStatus::Copied
+                                }
+                            };
+
+                            reports.push(FileReport {
+                                file_name: report_name.clone(),
+                                status,
+                                input_size: mkv.size(),
+                                output_size: fs::metadata(&output_path).map(| m | m.len()).unwrap_or_default(),
+                                duration: mkv.duration(),
+                                transcoded: transcode_video && !kept_source,
+                                audio_tracks,
+                                sub_tracks,
+                                attachments,
+                                decode_errors: 0,
+                                hash: output_hash,
+                                quality_score,
+                                elapsed_secs: file_timer.elapsed().as_secs()
+                            });
+                        }
+                    }
                 }
-                else if target_path.exists() {
-                    fs::remove_file(&target_path).expect("Failed to remove output file");
+                else if partial_path.exists() {
+                    fs::remove_file(&partial_path).expect("Failed to remove partial output file");
                 }
             }
+
+            if reports.len() == report_count {
+                reports.push(FileReport::unprocessed(report_name, FileStatus::Failed, mkv.size(), file_timer.elapsed().as_secs()));
+            }
+
+            self.current_target = None;
+
+            processed_bytes += mkv.size();
+            file_times.push(file_timer.elapsed().as_secs());
+
+            // Estimate by bytes instead of file count, episodes and movies in the same queue throw the latter way off.
+            // Empty files alone don't give a rate to go by.
+            if processed_bytes > 0 && processed_bytes < total_bytes {
+                let elapsed = total_timer.elapsed().as_secs_f64();
+                let remaining_secs = ((total_bytes - processed_bytes) as f64 / (processed_bytes as f64 / elapsed)) as u64;
+
+                info!(
+                    "Queue: {}/{} processed, about {} remaining.\n",
+                    ByteSize::b(processed_bytes),
+                    ByteSize::b(total_bytes),
+                    format_time(remaining_secs)
+                );
+            }
         }
 
         if self.files.len() > 1 {
             let elapsed_secs = total_timer.elapsed().as_secs();
-            info!("Finished processing all files in {}m{}s", elapsed_secs / 60, elapsed_secs % 60);
+            info!("Finished processing all files in {}", format_time(elapsed_secs));
+        }
+
+        // The first file runs on a cold disk cache, which throws off the average on small batches.
+        if file_times.len() > 1 {
+            let warm_times = &file_times[1..];
+            let warm_average = warm_times.iter().sum::<u64>() / warm_times.len() as u64;
+
+            let mut sorted_times = file_times.clone();
+            sorted_times.sort_unstable();
+            let median = sorted_times[sorted_times.len() / 2];
+
+            info!(
+                "Per file: {} median, {} average excluding the first file.",
+                format_time(median),
+                format_time(warm_average)
+            );
+        }
+
+        if self.dry_run && estimated_bytes.0 > 0 {
+            let (source_bytes, output_bytes) = estimated_bytes;
+            let reduction = (1.0 - output_bytes as f64 / source_bytes as f64) * 100.0;
+
+            info!(
+                "Estimated total: {} -> ~{} (~{reduction:.0}% reduction). This is a rough estimate, not a promise.",
+                ByteSize::b(source_bytes),
+                ByteSize::b(output_bytes)
+            );
+        }
+
+        if !reports.is_empty() {
+            report::log_summary(&reports);
+        }
+
+        if let Some(report_path) = self.report.as_ref() {
+            match report::write_report(report_path, self.report_format, &reports) {
+                Ok(()) => info!("Wrote the batch report to {}", report_path.to_string_lossy()),
+                Err(e) => warn!("Failed to write the batch report: {e}")
+            }
+        }
+
+        // The last file failing counts too, even with nothing left to stop.
+        if self.fail_fast && reports.iter().any(| r | r.status == FileStatus::Failed) {
+            return Err("a file failed with --fail-fast on".into());
+        }
+
+        if failed_assertions > 0 {
+            return Err(format!("{failed_assertions} file(s) failed --assert").into());
         }
 
         Ok(())
@@ -341,26 +2016,120 @@ impl Cruncher {
 
 fn main() {
     let args = args::AppArgs::parse();
+
+    if let Err(e) = args.validate() {
+        e.exit();
+    }
+
+    // Has to happen before anything gets printed, or it'd end up mixed with the output.
+    let stdout_output = {
+        if args.output_to_stdout() {
+            match take_stdout() {
+                Ok(stdout_output) => Some(stdout_output),
+                Err(e) => panic!("Failed to set up stdout for the output! {e}")
+            }
+        }
+        else {
+            None
+        }
+    };
+
     let _logger_handle = configure_log();
 
-    info!("Starting cruncher...\n");
+    if args.list() {
+        validate_input_dirs(&args.input_dirs());
+        list_files(&args);
+        return;
+    }
 
-    let intermediate = args.intermediate_dir().clone();
-    let mut cruncher = Cruncher::init(args);
+    info!("Starting cruncher...");
 
-    if cruncher.start_cruncher().is_err() {
-        error!("Exiting because of an error...");
+    // Encoder behavior changes between releases, keep a record of what produced the outputs.
+    for tool in ["ffmpeg", "ffprobe"] {
+        match tool_version(tool) {
+            Some(version) => info!("{version}"),
+            None => warn!("Couldn't get {tool}'s version, is it installed?")
+        }
+    }
 
-        if let Some(intermediate) = intermediate {
-            for entry in WalkDir::new(intermediate).max_depth(1).into_iter().filter_map(| f | f.ok()) {
-                let file_name = entry.file_name().to_string_lossy().to_string();
+    println!();
 
-                if entry.file_type().is_file() && file_name.to_lowercase().contains("mkv") {
-                    fs::remove_file(entry.path()).expect("Failed to remove intermediate file");
-                }
-            }
+    let assume_yes = args.yes();
+    // Taken before the first batch, anything showing up while it runs is fair game for watch mode.
+    let watch = {
+        if args.watch() {
+            let known: HashSet<PathBuf> = args.input_dirs().iter().flat_map(| dir | scan_dir(&args, dir)).collect();
+            Some((args.clone(), known))
+        }
+        else {
+            None
+        }
+    };
+
+    let mut cruncher = Cruncher::init(args, stdout_output);
+
+    if !cruncher.confirm_run(assume_yes) {
+        error!("Aborting, nothing was processed.");
+        std::process::exit(1);
+    }
+
+    let result = cruncher.start_cruncher();
+
+    // A bad batch shouldn't take the daemon down with it, the next one might go fine.
+    if let Some((cfg, known)) = watch {
+        if let Err(e) = result {
+            error!("Batch failed: {e}\n");
+            cruncher.clean_up_current_target();
         }
+
+        cruncher.watch(&cfg, known);
+    }
+    else if let Err(e) = result {
+        error!("Exiting because of an error: {e}");
+
+        cruncher.clean_up_current_target();
+        std::process::exit(1);
+    }
+}
+
+/// Points our own stdout at stderr, handing back the original for ffmpeg to write the output to.
+#[cfg(unix)]
+fn take_stdout() -> io::Result<fs::File> {
+    use std::os::unix::io::FromRawFd;
+
+    io::stdout().flush()?;
+
+    let fd = unsafe { libc::dup(libc::STDOUT_FILENO) };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
     }
+
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn take_stdout() -> io::Result<fs::File> {
+    Err(io::Error::other("writing to stdout is only supported on unix"))
+}
+
+/// The first line of `tool -version`, which has the version and build info.
+fn tool_version(tool: &str) -> Option<String> {
+    let output = Command::new(tool)
+        .arg("-version")
+        .output()
+        .ok()?
+    ;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout).lines().next().map(| line | line.trim().to_owned())
 }
 
 fn configure_log() -> LoggerHandle {
@@ -374,247 +2143,292 @@ fn configure_log() -> LoggerHandle {
         .expect("Failed to start Logger")
 }
 
-fn analyze_video(mkv: &MkvFile) -> bool {
-    // Don't transcode stuff that's too small, will probably nuke quality.
-    if ByteSize::b(mkv.size()) < ByteSize::mib(600) {
-        false
-    }
-    // If it has some size, only transcode if it's not on the target video codec.
-    else {
-        mkv.video_streams()[0].codec() != TARGET_CODEC
+fn validate_input_dirs(input_dirs: &[PathBuf]) {
+    for input_dir in input_dirs {
+        if !input_dir.exists() {
+            panic!("Input directory {} doesn't exist!", input_dir.to_string_lossy());
+        }
+
+        if !input_dir.is_dir() {
+            panic!("Input path {} is not a directory!", input_dir.to_string_lossy());
+        }
     }
 }
 
-fn analyze_sub_tracks(mkv: &MkvFile) -> Vec<(usize, &Stream)> {
-    let all_streams = mkv.subtitles_streams();
-    let stream_count = all_streams.len();
+fn find_files(cfg: &args::AppArgs) -> Vec<PathBuf> {
+    let mut files = Vec::new();
 
-    if stream_count == 1 {
-        info!("  Keeping all subs ({stream_count}).");
-        
-        return all_streams
-            .into_iter()
-            .enumerate()
-            .collect()
+    for input_dir in cfg.input_dirs() {
+        info!("Reading directory {}", input_dir.as_os_str().to_string_lossy());
+        files.extend(scan_dir(cfg, &input_dir));
     }
 
-    let mut preserved_streams: Vec<(usize, &Stream)> = all_streams
-        .into_iter()
-        .enumerate()
-        .map(| (i, s) | (i, s))
-        .collect()
-    ;
+    files
+}
 
-    preserved_streams.sort_unstable_by_key(|(_, s)| {
-        if s.stream_title().is_empty() {
-            s.stream_language()
-        }
-        else {
-            s.stream_title()
-        }
-    });
-    preserved_streams.dedup_by_key(| (_, s) | {
-        if s.stream_title().is_empty() {
-            s.stream_language()
+fn scan_dir(cfg: &args::AppArgs, input_dir: &Path) -> Vec<PathBuf> {
+    let max_depth = {
+        if cfg.recursive() {
+            usize::MAX
         }
         else {
-            s.stream_title()
+            1
         }
-    });
+    };
 
-    let has_ass = preserved_streams.iter()
-        .filter(|(_, s)| s.codec() == ASS_CODEC)
-        .count() > 0
-    ;
+    let name_filter = cfg.name_filter();
 
-    preserved_streams = preserved_streams
+    WalkDir::new(input_dir)
+        .max_depth(max_depth)
+        .sort_by(|a,b| a.file_name().to_ascii_lowercase().cmp(&b.file_name().to_ascii_lowercase()))
         .into_iter()
-        // Filter out unwanted sub tracks.
-        .filter(| (_, s) | {
-            let name = s.stream_title().to_lowercase();
+        .filter_map(| entry | entry.ok())
+        .filter(| entry | entry.file_type().is_file())
+        .filter(| entry | entry.file_name().to_string_lossy().contains(".mkv"))
+        .filter(| entry | {
+            let name_filter = match name_filter.as_ref() {
+                Some(name_filter) => name_filter,
+                None => return true
+            };
 
-            if name.contains("jap") || name.contains("jpn") || s.stream_language() == "jpn" {
-                true
-            }
-            else {
-                let mut keep = true;
+            let matches = name_filter.is_match(&entry.file_name().to_string_lossy());
 
-                for bad_word in BAD_SUB_WORDS {
-                    if name == bad_word || name.contains(bad_word) {
-                        keep = false;
-                        break;
-                    }
-                }
-                
-                keep
-            }
-        })
-        // Filter out unused languages.
-        .filter(| (_, s) | {
-            OK_SUB_LANGS.contains(&s.stream_language().as_str())
-        })
-        // Filter out PGS and other formats if we have ASS subs.
-        .filter(| (_, s) | {
-            if has_ass {
-                s.codec() == ASS_CODEC || s.stream_language() == "jpn"
-            }
-            else {
-                true
+            if !matches {
+                debug!("Skipping '{}', doesn't match the name filter.", entry.file_name().to_string_lossy());
             }
+
+            matches
         })
+        .map(| entry | entry.into_path())
         .collect()
-    ;
+}
 
-    if preserved_streams.len() < stream_count {
-        info!("  Keeping {}/{} subs.", preserved_streams.len(), stream_count);
+fn list_files(cfg: &args::AppArgs) {
+    let input_dirs = cfg.input_dirs();
+    let probe_options = ProbeOptions::new(cfg);
 
-        for (_, s) in preserved_streams.iter() {
-            let stream_title = s.stream_title();
-            
-            let stream_name = {
-                if stream_title.is_empty() {
-                    "Untitled track"
-                }
-                else {
-                    stream_title.as_str()
+    for file in find_files(cfg) {
+        let file_name = relative_output_path(&input_dirs, &file, false);
+        let file_name = file_name.to_string_lossy();
+
+        match ffprobe::probe_file(&file, &probe_options) {
+            Ok(mkv) => {
+                let video_codec = mkv.primary_video_stream().map(| (_, s) | s.codec().to_owned()).unwrap_or_default();
+
+                println!(
+                    "{file_name} | {} | {} | {video_codec} | {} audio | {} subs",
+                    ByteSize::b(mkv.size()),
+                    format_time(mkv.duration() as u64),
+                    mkv.audio_streams().len(),
+                    mkv.subtitles_streams().len()
+                );
+
+                let mut tags: Vec<(&String, &String)> = mkv.tags().iter().collect();
+                tags.sort_unstable();
+
+                for (key, value) in tags {
+                    println!("    {key}: {value}");
                 }
-            };
+            }
+            Err(e) => println!("{file_name} | Failed to probe: {e}")
+        }
+    }
+}
 
-            info!("      {stream_name} ({})", s.codec());
+/// The metadata cleanup applied to every output.
+fn metadata_arguments(mkv: &MkvFile, keep_metadata: &[String], title: Option<&str>, extra_metadata: &[(String, String)]) -> Vec<String> {
+    let mut arguments = vec![
+        // Remove title metadata from the file
+        String::from("-metadata"), String::from("title="),
+        // and the video track
+        String::from("-metadata:s:v"), String::from("title="),
+        // *and* the audio track.
+        String::from("-metadata:s:a"), String::from("title="),
+        // Some people add language metadata to video streams for some reason.
+        // Don't be like those people, you throw off my shit scripts.
+        String::from("-metadata:s:v"), String::from("language=und"),
+    ];
+
+    // Put back whatever the user asked to keep, after the stripping above.
+    for key in keep_metadata.iter() {
+        if let Some((key, value)) = mkv.tag(key) {
+            arguments.push(String::from("-metadata"));
+            arguments.push(format!("{key}={value}"));
         }
     }
-    else {
-        info!("  Keeping all subs ({stream_count}).");
+
+    if let Some(title) = title {
+        arguments.push(String::from("-metadata"));
+        arguments.push(format!("title={title}"));
+    }
+
+    // Last, so they win over everything above.
+    for (key, value) in extra_metadata.iter() {
+        arguments.push(String::from("-metadata"));
+        arguments.push(format!("{key}={value}"));
     }
 
-    preserved_streams
+    arguments
 }
 
-fn analyze_audio_tracks(mkv: &MkvFile) -> Vec<(usize, &Stream)> {
-    let all_streams = mkv.audio_streams();
-    let stream_count = all_streams.len();
+/// A title for `file` out of its name, without the extension and any [tags] (release groups, checksums, etc).
+fn title_from_file_name(file: &Path) -> String {
+    let stem = file.file_stem().map(| s | s.to_string_lossy().to_string()).unwrap_or_default();
 
-    if stream_count == 1 {
-        info!("  Keeping all audio tracks ({stream_count}).");
+    let mut title = String::with_capacity(stem.len());
+    let mut depth = 0;
 
-        return all_streams
-            .into_iter()
-            .enumerate()
-            .collect()
+    for c in stem.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 => title.push(c),
+            _ => {}
+        }
     }
 
-    let mut preserved_streams: Vec<(usize, &Stream)> = all_streams
-        .into_iter()
-        .enumerate()
-        // Filter non-japanese, leave undefined just in case.
-        .filter(| (_, s) | {
-            let l = s.stream_language();
-            l.is_empty() || l == "jpn" || l == "chi" || l == "und"
-        })
-        // Fallback filter + nuke commentary tracks.
-        .filter(| (_, s) | {
-            let stream_name = s.stream_title().to_lowercase();
-            !stream_name.contains("commentary") && !stream_name.contains("description") && (!stream_name.contains("eng") || !stream_name.contains("english"))
-        })
-        .collect()
-    ;
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
 
-    // Try to nuke potential 5.1 tracks if we still have more than one track.
-    if preserved_streams.len() > 1 {
-        let jpn_stereo: Vec<(usize, &Stream)> = preserved_streams.clone()
-            .into_iter()
-            .filter( | (_, s) | {
-                // == 0 is a fallback in case parsing drops the ball.
-                s.channels() == 2 || s.channels() == 0
-            })
-            .collect()
-        ;
+    // A name that's nothing but tags is still better than no title.
+    if title.is_empty() {
+        stem
+    }
+    else {
+        title
+    }
+}
 
-        if !jpn_stereo.is_empty() {
-            preserved_streams = jpn_stereo;
+fn color_arguments(primaries: &str, transfer: &str, space: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+
+    for (option, value) in [("-color_primaries", primaries), ("-color_trc", transfer), ("-colorspace", space)] {
+        if !value.is_empty() && value != "unknown" {
+            arguments.push(String::from(option));
+            arguments.push(String::from(value));
         }
     }
 
-    if preserved_streams.len() < stream_count {
-        info!("  Keeping {}/{stream_count} audio tracks.", preserved_streams.len());
-
-        for (_, s) in preserved_streams.iter() {
-            let stream_title = s.stream_title();
+    arguments
+}
 
-            let stream_name = {
-                if stream_title.is_empty() {
-                    "Untitled track"
-                }
-                else {
-                    stream_title.as_str()
-                }
-            };
+/// Whether `metadata_arguments` would actually change anything on `mkv`.
+fn needs_metadata_changes(mkv: &MkvFile, keep_metadata: &[String], title: Option<&str>) -> bool {
+    let keeps_title = keep_metadata.iter().any(| key | key.eq_ignore_ascii_case("title"));
 
-            info!("      {stream_name} ({})", s.codec());
+    if let Some(title) = title {
+        if mkv.tag("title").map(| (_, value) | value) != Some(title) {
+            return true;
         }
     }
-    else {
-        info!("  Keeping all audio tracks ({stream_count}).");
+
+    let has_title = mkv.tag("title").map(| (_, value) | !value.is_empty()).unwrap_or(false) && title.is_none();
+    let video_tagged = mkv.video_streams().iter().any(| s | !s.stream_title().is_empty() || s.stream_language() != "und");
+    let audio_titled = mkv.audio_streams().iter().any(| s | !s.stream_title().is_empty());
+
+    (has_title && !keeps_title) || video_tagged || audio_titled
+}
+
+/// Where a file should end up, relative to the output (or intermediate) directory.
+fn relative_output_path(input_dirs: &[PathBuf], file: &Path, flatten: bool) -> PathBuf {
+    let relative = input_dirs.iter().find_map(| input_dir | file.strip_prefix(input_dir).ok());
+
+    match relative {
+        Some(relative) if !flatten => relative.to_path_buf(),
+        _ => PathBuf::from(file.file_name().unwrap_or_default())
     }
+}
 
-    preserved_streams
+/// Where ffmpeg writes `target` until it's done, `Show - 01.mkv` goes to `.Show - 01.mkv.partial`.
+fn partial_output_path(target: &Path) -> PathBuf {
+    let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{file_name}.partial"))
 }
 
-fn analyze_attachments(mkv: &MkvFile) -> Vec<(usize, &Stream)> {
-    let all_attachments = mkv.attachments();
-    let attachment_count = all_attachments.len();
+/// Partial outputs, --keep-original copies and segments, the files a run leaves next to its outputs.
+fn is_own_file(path: &Path) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
 
-    let mut preserved_attachments: Vec<(usize, &Stream)> = all_attachments
-        .into_iter()
-        .enumerate()
-        .filter(| (_, a) | {
-            let name = a.stream_title().to_lowercase();
-            // Preserve fonts and files without extensions.
-            name.contains("ttf") || name.contains("ttc") || name.contains("otf") || !name.contains('.')
-        })
-        .collect()
-    ;
+    path.extension().map(| extension | extension == "partial").unwrap_or(false)
+        || stem.ends_with(".original")
+        || path.components().any(| component | component.as_os_str().to_string_lossy().ends_with(".segments"))
+}
+
+/// Where --keep-original puts the source for `output_path`, `Show - 01.mkv` goes to `Show - 01.original.mkv`.
+fn original_copy_path(output_path: &Path, source: &Path) -> PathBuf {
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match source.extension() {
+        Some(extension) => output_path.with_file_name(format!("{stem}.original.{}", extension.to_string_lossy())),
+        None => output_path.with_file_name(format!("{stem}.original"))
+    }
+}
+
+/// Whether `a` and `b` are the same file on disk, through whatever links or relative paths lead to them.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
+    }
+}
 
-    let preserved = preserved_attachments.len();
+fn svt_log_level(level: Option<SvtLogLevel>) -> Option<String> {
+    match level {
+        Some(level) => Some(level.as_str().to_owned()),
+        // Leave it alone if the user already set it up on their environment.
+        None if std::env::var_os("SVT_LOG").is_some() => None,
+        None => Some(SvtLogLevel::Fatal.as_str().to_owned())
+    }
+}
 
-    preserved_attachments.sort_unstable_by_key(| (_, a) | a.stream_title());
-    preserved_attachments.dedup_by_key(| (_, a) | a.stream_title());
+/// Makes `path` independent of the working directory, so it survives ffmpeg being started elsewhere.
+fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(| _ | path.to_path_buf())
+}
 
-    if preserved < attachment_count {
-        info!("  Keeping {preserved}/{attachment_count} attachments.");
+fn format_time(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h{}m{}s", secs / 3600, (secs % 3600) / 60, secs % 60)
     }
     else {
-        info!("  Keeping all attachments ({attachment_count}).");
+        format!("{}m{}s", secs / 60, secs % 60)
     }
+}
 
-    preserved_attachments
+fn output_bit_depth(source_depth: u8, mode: OutputBitDepth) -> u8 {
+    match mode {
+        // SVT-AV1 tops out at 10-bit.
+        OutputBitDepth::Source => source_depth.min(10),
+        OutputBitDepth::Eight => 8,
+        OutputBitDepth::Ten => 10
+    }
 }
 
-const ASS_CODEC: &str = "ass";
-const TARGET_CODEC: &str = "av1";
-
-const OK_SUB_LANGS: [&str; 5] = [
-    "eng",
-    "enm",
-    "jpn",
-    "spa",
-    "und"
-];
-
-const BAD_SUB_WORDS: [&str; 8] = [
-    "s&s",
-    "signs",
-    "songs",
-    "spain",
-    "closed",
-    "captions",
-    "closed captions",
-    "commentary"
-];
-
-const LOSSLESS_AUDIO_CODECS: [&str; 4] = [
-    "dts",
-    "flac",
-    "truehd",
-    "pcm_s24le"
-];
+// How far apart (in seconds) the output and source durations can be before warning about it.
+const DURATION_TOLERANCE: f64 = 1.0;
+
+// How long (in seconds) to watch the batch's files for growth before starting.
+const GROWTH_CHECK_INTERVAL: u64 = 2;
+
+// How recently (in seconds) a file has to have been modified to get checked for growth.
+const RECENT_WRITE_WINDOW: u64 = 60;
+
+// How often (in seconds) --watch looks for new files.
+const WATCH_INTERVAL: u64 = 10;
+
+// How many of the precheck's decode errors make it to the log, the rest are just counted.
+const PRECHECK_LOGGED_ERRORS: usize = 5;
+
+// How far apart (in fps) the source and --fps rates can be before converting.
+const FPS_TOLERANCE: f64 = 0.01;
+
+// Roughly how far apart keyframes go when --fps changes the rate.
+const GOP_SECONDS: f64 = 5.0;
+
+// How far (in °C) under --max-temp the CPU has to get before resuming.
+const TEMP_HYSTERESIS: f64 = 5.0;
+
+// How often (in seconds) to check the temperature while paused.
+const THERMAL_POLL_INTERVAL: u64 = 15;
+
+// Transcoded audio always ends up stereo.
+const OUTPUT_AUDIO_CHANNELS: u64 = 2;
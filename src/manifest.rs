@@ -0,0 +1,72 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+
+/// Keeps track of the files that were fully processed, one name per line.
+pub struct Manifest {
+    // None when there's nowhere to keep it, like when writing to stdout.
+    path: Option<PathBuf>,
+    entries: HashSet<String>,
+    // Set on a new manifest, whose file still has the last batch's entries until processing starts.
+    reset_pending: bool
+}
+
+impl Manifest {
+    /// Opens the manifest in `output_dir`, starting a new one unless `resume` is set.
+    /// A new manifest's file is only cleared by `begin`, so runs that end up not processing anything leave it alone.
+    pub fn open(output_dir: &Path, resume: bool) -> io::Result<Manifest> {
+        let mut path = output_dir.to_path_buf();
+        path.push(MANIFEST_FILE_NAME);
+
+        let entries = {
+            if resume && path.exists() {
+                fs::read_to_string(&path)?
+                    .lines()
+                    .filter(| line | !line.is_empty())
+                    .map(| line | line.to_owned())
+                    .collect()
+            }
+            else {
+                HashSet::new()
+            }
+        };
+
+        Ok(Manifest { path: Some(path), entries, reset_pending: !resume })
+    }
+
+    /// A manifest that doesn't keep track of anything.
+    pub fn disabled() -> Manifest {
+        Manifest { path: None, entries: HashSet::new(), reset_pending: false }
+    }
+
+    /// Clears the file of a new manifest, once files are actually about to be processed. Only the first call does anything.
+    pub fn begin(&mut self) -> io::Result<()> {
+        if let Some(path) = self.path.as_ref().filter(| _ | self.reset_pending) {
+            fs::write(path, "")?;
+        }
+
+        self.reset_pending = false;
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains(name)
+    }
+
+    pub fn record(&mut self, name: &str) -> io::Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(())
+        };
+
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        writeln!(file, "{name}")?;
+
+        self.entries.insert(name.to_owned());
+        Ok(())
+    }
+}
+
+// Lives in the output dir, next to the files it lists.
+const MANIFEST_FILE_NAME: &str = "cruncher_manifest.txt";
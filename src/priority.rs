@@ -0,0 +1,57 @@
+use std::process::Command;
+
+/// Makes `command` run at `niceness`, and on Linux with a matching IO priority.
+/// Applied on the child right before exec, so it doesn't touch our own priority.
+#[cfg(unix)]
+pub fn set_niceness(command: &mut Command, niceness: i32) {
+    use std::os::unix::process::CommandExt;
+
+    unsafe {
+        command.pre_exec(move || {
+            // Absolute, unlike nice(1). Asking for less than we already run at fails without root,
+            // which is no reason to skip the encode, ffmpeg just keeps our niceness then.
+            libc::setpriority(libc::PRIO_PROCESS, 0, niceness);
+
+            set_io_priority(niceness);
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn set_niceness(_command: &mut Command, _niceness: i32) {}
+
+// Best effort as well, not every IO scheduler cares about priorities.
+#[cfg(target_os = "linux")]
+fn set_io_priority(niceness: i32) {
+    let priority = {
+        if niceness >= IDLE_NICENESS {
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT
+        }
+        else {
+            // Same mapping the kernel uses for processes without an explicit IO priority.
+            (IOPRIO_CLASS_BEST_EFFORT << IOPRIO_CLASS_SHIFT) | ((niceness + 20) / 5)
+        }
+    };
+
+    unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority);
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn set_io_priority(_niceness: i32) {}
+
+// From linux/ioprio.h, libc doesn't have them.
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: i32 = 1;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_BEST_EFFORT: i32 = 2;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_IDLE: i32 = 3;
+
+// Niceness at which ffmpeg only gets IO time nobody else wants.
+#[cfg(target_os = "linux")]
+const IDLE_NICENESS: i32 = 19;
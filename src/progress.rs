@@ -0,0 +1,105 @@
+use log::*;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Tracks ffmpeg's progress on a file, either with an animated bar or with plain log lines.
+pub struct FileProgress {
+    bar: Option<ProgressBar>,
+
+    total: u64,
+    speed: String,
+    fps: String,
+    last_report: u64
+}
+
+impl FileProgress {
+    pub fn new(total: u64, animated: bool, file_name: &str) -> FileProgress {
+        let bar = {
+            if animated {
+                // indicatif already leaves colours out when NO_COLOR is set.
+                let bar = ProgressBar::new(total);
+
+                bar.set_style(
+                    ProgressStyle::with_template("{prefix} {percent}% {wide_bar} ({msg} - Elapsed: {elapsed_precise})")
+                    .unwrap()
+                    .progress_chars("##-")
+                );
+                bar.set_prefix(truncate_name(file_name));
+
+                Some(bar)
+            }
+            else {
+                None
+            }
+        };
+
+        FileProgress {
+            bar,
+
+            total,
+            speed: String::new(),
+            fps: String::new(),
+            last_report: 0
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: &str) {
+        self.speed = speed.trim().to_owned();
+
+        if let Some(bar) = self.bar.as_ref() {
+            bar.set_message(self.status());
+        }
+    }
+
+    pub fn set_fps(&mut self, fps: &str) {
+        // Stream copies and audio-only work report 0, nothing worth showing there.
+        if fps.trim().parse::<f64>().unwrap_or_default() > 0.0 {
+            self.fps = format!("{} fps", fps.trim());
+        }
+        else {
+            self.fps.clear();
+        }
+    }
+
+    fn status(&self) -> String {
+        if self.fps.is_empty() {
+            self.speed.clone()
+        }
+        else {
+            format!("{}, {}", self.fps, self.speed)
+        }
+    }
+
+    pub fn set_position(&mut self, position: u64) {
+        if let Some(bar) = self.bar.as_ref() {
+            bar.set_position(position);
+        }
+        else if let Some(percent) = (position.min(self.total) * 100).checked_div(self.total) {
+            if percent >= self.last_report + PLAIN_REPORT_STEP {
+                self.last_report = percent - (percent % PLAIN_REPORT_STEP);
+                info!("  {percent}% at {}", self.status());
+            }
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = self.bar.as_ref() {
+            bar.finish();
+            println!("\n");
+        }
+    }
+}
+
+// Long release names would push the bar onto a second line.
+fn truncate_name(file_name: &str) -> String {
+    if file_name.chars().count() <= MAX_NAME_LENGTH {
+        return file_name.to_owned();
+    }
+
+    let truncated: String = file_name.chars().take(MAX_NAME_LENGTH - 3).collect();
+    format!("{truncated}...")
+}
+
+// How often (in percent) plain progress lines get logged.
+const PLAIN_REPORT_STEP: u64 = 10;
+// How many characters of the file name the bar shows.
+const MAX_NAME_LENGTH: usize = 32;
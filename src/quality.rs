@@ -0,0 +1,48 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::args::QualityMetric;
+
+/// Compares the video of `output` against `source`'s video stream `source_video_idx`, returning the resulting score.
+/// ffmpeg runs on `temp_dir` when given, so the filters' scratch files end up there.
+pub fn measure_quality(metric: QualityMetric, output: &Path, source: &Path, source_video_idx: usize, temp_dir: Option<&Path>) -> Option<f64> {
+    let filter = match metric {
+        QualityMetric::Ssim => format!("[0:v:0][1:v:{source_video_idx}]ssim"),
+        QualityMetric::Vmaf => format!("[0:v:0][1:v:{source_video_idx}]libvmaf"),
+    };
+
+    let mut ffmpeg = Command::new("ffmpeg");
+
+    if let Some(temp_dir) = temp_dir {
+        ffmpeg.current_dir(temp_dir);
+    }
+
+    // The scores are only printed at the info log level, on stderr.
+    let result = ffmpeg
+        .args(["-hide_banner", "-nostats", "-loglevel", "info"])
+        .arg("-i")
+        .arg(crate::absolute_path(output))
+        .arg("-i")
+        .arg(crate::absolute_path(source))
+        .args(["-lavfi", &filter, "-f", "null", "-"])
+        .output()
+        .ok()?
+    ;
+
+    let stderr = String::from_utf8_lossy(&result.stderr);
+
+    for line in stderr.lines() {
+        let score = match metric {
+            // [Parsed_ssim_0 @ 0x...] SSIM Y:0.987 (18.9) U:0.991 (20.5) V:0.990 (20.3) All:0.988 (19.3)
+            QualityMetric::Ssim => line.split_once("All:").map(| (_, rest) | rest.split_whitespace().next().unwrap_or_default()),
+            // [Parsed_libvmaf_0 @ 0x...] VMAF score: 95.123456
+            QualityMetric::Vmaf => line.split_once("VMAF score:").map(| (_, rest) | rest.trim()),
+        };
+
+        if let Some(score) = score.and_then(| s | s.parse::<f64>().ok()) {
+            return Some(score);
+        }
+    }
+
+    None
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use log::*;
+
+use crate::ffprobe::mkv::MkvFile;
+
+/// Applies the metadata cleanup to `file` without touching its streams, writing the result to `output`.
+/// Uses mkvpropedit to edit a plain copy when it's installed (or the file itself, when `output` is `file`),
+/// falls back to a stream-copy remux otherwise. Extra metadata always goes through the remux, mkvpropedit only sets
+/// arbitrary tags from an XML file.
+pub fn rename_only(file: &Path, mkv: &MkvFile, output: &Path, keep_metadata: &[String], title: Option<&str>, extra_metadata: &[(String, String)]) -> io::Result<()> {
+    if extra_metadata.is_empty() && crate::disk::has_tool("mkvpropedit") {
+        info!("  Editing metadata with mkvpropedit.");
+
+        // mkvpropedit edits in place, copying a file onto itself would only truncate it.
+        if !crate::same_file(file, output) {
+            fs::copy(file, output)?;
+        }
+
+        run(Command::new("mkvpropedit").arg(output).args(mkvpropedit_arguments(mkv, keep_metadata, title)))
+    }
+    else {
+        if extra_metadata.is_empty() {
+            info!("  mkvpropedit isn't available, remuxing with ffmpeg instead.");
+        }
+        else {
+            info!("  Adding metadata, remuxing with ffmpeg.");
+        }
+
+        // ffmpeg can't write over its own input, the remux goes next to it and replaces it after.
+        // Named like any other partial output, so nothing scanning for .mkv files picks it up halfway.
+        let in_place = crate::same_file(file, output);
+        let remux_path = {
+            if in_place {
+                crate::partial_output_path(output)
+            }
+            else {
+                output.to_path_buf()
+            }
+        };
+
+        let result = run(
+            Command::new("ffmpeg")
+                .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+                .arg(file)
+                .args(["-map", "0", "-c", "copy"])
+                .args(crate::metadata_arguments(mkv, keep_metadata, title, extra_metadata))
+                .args(["-f", "matroska"])
+                .arg(&remux_path)
+        );
+
+        if in_place {
+            match result {
+                Ok(()) => fs::rename(&remux_path, output)?,
+                Err(_) if remux_path.exists() => {
+                    if let Err(e) = fs::remove_file(&remux_path) {
+                        warn!("  Failed to remove the partial remux: {e}");
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+
+        result
+    }
+}
+
+fn mkvpropedit_arguments(mkv: &MkvFile, keep_metadata: &[String], title: Option<&str>) -> Vec<String> {
+    let mut arguments = Vec::new();
+
+    if let Some(title) = title {
+        arguments.extend([String::from("--edit"), String::from("info"), String::from("--set"), format!("title={title}")]);
+    }
+    else if !keep_metadata.iter().any(| key | key.eq_ignore_ascii_case("title")) {
+        arguments.extend([String::from("--edit"), String::from("info"), String::from("--delete"), String::from("title")]);
+    }
+
+    // mkvpropedit's track selectors are 1-based.
+    for idx in 1..=mkv.video_streams().len() {
+        arguments.extend([String::from("--edit"), format!("track:v{idx}")]);
+        arguments.extend([String::from("--delete"), String::from("name")]);
+        arguments.extend([String::from("--set"), String::from("language=und")]);
+    }
+
+    for idx in 1..=mkv.audio_streams().len() {
+        arguments.extend([String::from("--edit"), format!("track:a{idx}")]);
+        arguments.extend([String::from("--delete"), String::from("name")]);
+    }
+
+    arguments
+}
+
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.stdout(Stdio::null()).status()?;
+
+    if status.success() {
+        Ok(())
+    }
+    else {
+        Err(io::Error::other(format!("process exited with {status}")))
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::*;
+use serde::Serialize;
+use bytesize::ByteSize;
+
+use crate::args::ReportFormat;
+
+#[derive(Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Encoded,
+    Copied,
+    Skipped,
+    Failed
+}
+
+impl FileStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileStatus::Encoded => "encoded",
+            FileStatus::Copied => "copied",
+            FileStatus::Skipped => "skipped",
+            FileStatus::Failed => "failed",
+        }
+    }
+}
+
+/// The outcome of processing a single file, as written to the batch report.
+#[derive(Serialize)]
+pub struct FileReport {
+    pub file_name: String,
+    pub status: FileStatus,
+    pub input_size: u64,
+    pub output_size: u64,
+    pub duration: f64,
+    pub transcoded: bool,
+    pub audio_tracks: usize,
+    pub sub_tracks: usize,
+    pub attachments: usize,
+    // What --precheck turned up, always 0 without it.
+    pub decode_errors: usize,
+    // The output's, with --hash. Missing for skipped and failed files.
+    pub hash: Option<String>,
+    // The --measure-quality score, for encoded files that got measured.
+    pub quality_score: Option<f64>,
+    pub elapsed_secs: u64
+}
+
+impl FileReport {
+    /// A file that never made it to an output.
+    pub fn unprocessed(file_name: String, status: FileStatus, input_size: u64, elapsed_secs: u64) -> FileReport {
+        FileReport {
+            file_name,
+            status,
+            input_size,
+            output_size: 0,
+            duration: 0.0,
+            transcoded: false,
+            audio_tracks: 0,
+            sub_tracks: 0,
+            attachments: 0,
+            decode_errors: 0,
+            hash: None,
+            quality_score: None,
+            elapsed_secs
+        }
+    }
+
+    pub fn savings_percent(&self) -> f64 {
+        // Nothing was written for skipped and failed files.
+        if self.input_size == 0 || self.output_size == 0 {
+            return 0.0;
+        }
+
+        (1.0 - self.output_size as f64 / self.input_size as f64) * 100.0
+    }
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    #[serde(flatten)]
+    report: &'a FileReport,
+    savings_percent: f64
+}
+
+/// Logs a table with every file's outcome, failures stand out as errors.
+pub fn log_summary(reports: &[FileReport]) {
+    info!("Summary:");
+    info!("  {:<7} | {:>23} | {:>9} | File", "Status", "Size", "Time");
+
+    for report in reports {
+        let size = {
+            if report.output_size > 0 {
+                format!("{} -> {}", ByteSize::b(report.input_size), ByteSize::b(report.output_size))
+            }
+            else {
+                ByteSize::b(report.input_size).to_string()
+            }
+        };
+
+        let line = format!(
+            "  {:<7} | {size:>23} | {:>9} | {}",
+            report.status.as_str(),
+            crate::format_time(report.elapsed_secs),
+            report.file_name
+        );
+
+        if report.status == FileStatus::Failed {
+            error!("{line}");
+        }
+        else {
+            info!("{line}");
+        }
+    }
+
+    println!();
+}
+
+pub fn write_report(path: &Path, format: ReportFormat, reports: &[FileReport]) -> io::Result<()> {
+    let contents = match format {
+        ReportFormat::Json => to_json(reports)?,
+        ReportFormat::Csv => to_csv(reports)
+    };
+
+    fs::write(path, contents)
+}
+
+fn to_json(reports: &[FileReport]) -> io::Result<String> {
+    let entries: Vec<JsonEntry> = reports.iter()
+        .map(| report | JsonEntry { report, savings_percent: report.savings_percent() })
+        .collect()
+    ;
+
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+fn to_csv(reports: &[FileReport]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+
+    for report in reports {
+        csv.push_str(&format!(
+            "{},{},{},{},{:.2},{:.3},{},{},{},{},{},{},{},{}\n",
+            csv_field(&report.file_name),
+            report.status.as_str(),
+            report.input_size,
+            report.output_size,
+            report.savings_percent(),
+            report.duration,
+            report.transcoded,
+            report.audio_tracks,
+            report.sub_tracks,
+            report.attachments,
+            report.decode_errors,
+            report.hash.as_deref().unwrap_or_default(),
+            report.quality_score.map(| score | format!("{score:.4}")).unwrap_or_default(),
+            report.elapsed_secs
+        ));
+    }
+
+    csv
+}
+
+// File names are the only free-form column, and release names love commas.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+    else {
+        value.to_owned()
+    }
+}
+
+const CSV_HEADER: &str = "file_name,status,input_size,output_size,savings_percent,duration,transcoded,audio_tracks,sub_tracks,attachments,decode_errors,hash,quality_score,elapsed_secs";
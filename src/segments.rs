@@ -0,0 +1,127 @@
+use std::fs;
+use std::io;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use log::*;
+use seahash::SeaHasher;
+
+/// The video of a single file, encoded in pieces that survive the process dying halfway through.
+pub struct SegmentedEncode {
+    dir: PathBuf,
+    count: u8
+}
+
+impl SegmentedEncode {
+    /// Segments for `file` go in their own directory under `scratch_dir`, keyed by the file's path, size and mtime,
+    /// so a later run over the same file finds whatever the last one finished. Same-named files in other folders,
+    /// or a source that changed since, get a fresh start.
+    pub fn new(scratch_dir: &Path, file: &Path, count: u8) -> SegmentedEncode {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+
+        let mut hasher = SeaHasher::new();
+        hasher.write(crate::absolute_path(file).to_string_lossy().as_bytes());
+
+        if let Ok(metadata) = fs::metadata(file) {
+            hasher.write_u64(metadata.len());
+
+            if let Ok(modified) = metadata.modified().and_then(| m | m.duration_since(UNIX_EPOCH).map_err(io::Error::other)) {
+                hasher.write_u128(modified.as_nanos());
+            }
+        }
+
+        SegmentedEncode {
+            dir: crate::absolute_path(scratch_dir).join(format!(".{file_name}.{:016x}.segments", hasher.finish())),
+            count
+        }
+    }
+
+    /// The ffconcat list that stitches the segments back together, for ffmpeg's concat demuxer.
+    pub fn concat_list(&self) -> PathBuf {
+        self.dir.join("segments.ffconcat")
+    }
+
+    /// Encodes video stream `video_idx` of `file` with `video_arguments`, one segment at a time.
+    /// Segments that already exist are left alone, and the concat list is written once all of them are done.
+    pub fn encode(&self, file: &Path, duration: f64, video_idx: usize, video_arguments: &[String], svt_log_level: Option<&str>, nice: Option<i32>) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let segment_length = duration / self.count as f64;
+        let mut segments = Vec::new();
+
+        for n in 0..self.count {
+            let segment_path = self.dir.join(format!("segment_{n:03}_of_{:03}.mkv", self.count));
+
+            if segment_path.exists() {
+                info!("  Segment {}/{} already encoded, skipping.", n + 1, self.count);
+
+                segments.push(segment_path);
+                continue;
+            }
+
+            info!("  Encoding segment {}/{}...", n + 1, self.count);
+
+            // Written under another name first, a segment that exists is always a finished one.
+            let partial_path = segment_path.with_extension("partial.mkv");
+            let mut ffmpeg_process = Command::new("ffmpeg");
+
+            ffmpeg_process.args(["-hide_banner", "-loglevel", "error", "-nostats", "-y"]);
+
+            // Seeking on the input is fast, and exact anyway since the video gets decoded.
+            if n > 0 {
+                ffmpeg_process.args(["-ss", &(segment_length * n as f64).to_string()]);
+            }
+
+            ffmpeg_process
+                .arg("-i")
+                .arg(crate::absolute_path(file))
+            ;
+
+            // The last segment runs to the end, whatever rounding did to the length.
+            if n + 1 < self.count {
+                ffmpeg_process.args(["-t", &segment_length.to_string()]);
+            }
+
+            ffmpeg_process
+                .args(["-map", &format!("0:v:{video_idx}"), "-map_metadata", "-1", "-map_chapters", "-1"])
+                .args(video_arguments)
+                .arg(&partial_path)
+                .stdout(Stdio::null())
+            ;
+
+            if let Some(level) = svt_log_level {
+                ffmpeg_process.env("SVT_LOG", level);
+            }
+
+            if let Some(niceness) = nice {
+                crate::priority::set_niceness(&mut ffmpeg_process, niceness);
+            }
+
+            let status = ffmpeg_process.status()?;
+
+            if !status.success() {
+                return Err(io::Error::other(format!("ffmpeg exited with {status} on segment {}", n + 1)));
+            }
+
+            fs::rename(&partial_path, &segment_path)?;
+            segments.push(segment_path);
+        }
+
+        let mut list = String::from("ffconcat version 1.0\n");
+
+        for segment in segments {
+            list.push_str(&format!("file '{}'\n", segment.to_string_lossy().replace('\'', "'\\''")));
+        }
+
+        fs::write(self.concat_list(), list)
+    }
+
+    /// Removes the segments, once the output they went into is done.
+    pub fn clean_up(&self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            warn!("  Failed to remove the segments at {}: {e}", self.dir.to_string_lossy());
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use std::process::{Command, Stdio};
+
+use log::*;
+use bytesize::ByteSize;
+
+use crate::encoder::EncoderSettings;
+use crate::ffprobe::mkv::MkvFile;
+
+/// Encodes a sample of the file's video at each CRF, reporting the resulting size and encode time.
+/// SVT-AV1 is kept quiet unless `svt_log_level` says otherwise, its banner would break up the table.
+#[allow(clippy::too_many_arguments)]
+pub fn crf_sweep(file: &Path, mkv: &MkvFile, video_idx: usize, encoder: &EncoderSettings, pix_fmt: &str, crfs: &[u8], sample_length: u64, scratch_dir: &Path, svt_log_level: Option<&str>, nice: Option<i32>) {
+    let sample_length = (sample_length as f64).min(mkv.duration());
+    // Grab the sample from the middle, intros and credits aren't very representative.
+    let sample_start = ((mkv.duration() - sample_length) / 2.0).max(0.0);
+
+    info!("  Running CRF sweep on a {sample_length:.0}s sample.");
+    info!("      CRF | Sample size | Encode time | Estimated full size");
+
+    for crf in crfs {
+        let mut settings = encoder.clone();
+        settings.set_crf(*crf);
+
+        let mut sample_path = crate::absolute_path(scratch_dir);
+        sample_path.push(format!(".crf_sweep_{crf}.mkv"));
+
+        let timer = Instant::now();
+
+        let mut ffmpeg_process = Command::new("ffmpeg");
+
+        if let Some(niceness) = nice {
+            crate::priority::set_niceness(&mut ffmpeg_process, niceness);
+        }
+
+        let status = ffmpeg_process
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-ss", &sample_start.to_string()])
+            .arg("-i")
+            .arg(crate::absolute_path(file))
+            .args(["-t", &sample_length.to_string()])
+            .args(["-map", &format!("0:v:{video_idx}")])
+            .args(settings.ffmpeg_arguments())
+            .args(["-pix_fmt", pix_fmt])
+            .arg(&sample_path)
+            .current_dir(scratch_dir)
+            .env("SVT_LOG", svt_log_level.unwrap_or("fatal"))
+            .stdout(Stdio::null())
+            .status()
+        ;
+
+        let elapsed = timer.elapsed().as_secs();
+
+        match status {
+            Ok(status) if status.success() => {
+                let sample_size = fs::metadata(&sample_path).map(| m | m.len()).unwrap_or_default();
+                let estimated_size = (sample_size as f64 * (mkv.duration() / sample_length)) as u64;
+
+                info!(
+                    "      {crf:>3} | {:>11} | {:>11} | ~{}",
+                    ByteSize::b(sample_size).to_string(),
+                    crate::format_time(elapsed),
+                    ByteSize::b(estimated_size)
+                );
+            }
+            Ok(status) => warn!("      {crf:>3} | ffmpeg exited with {status}"),
+            Err(e) => warn!("      {crf:>3} | Failed to run ffmpeg: {e}")
+        }
+
+        if sample_path.exists() {
+            if let Err(e) = fs::remove_file(&sample_path) {
+                warn!("  Failed to remove sample file {}: {e}", sample_path.to_string_lossy());
+            }
+        }
+    }
+}
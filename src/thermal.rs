@@ -0,0 +1,23 @@
+/// The hottest thermal zone's temperature, in °C. None when there's nothing to read it from.
+/// Not every zone is the CPU, but on the small boxes this matters for the hottest one usually is.
+#[cfg(target_os = "linux")]
+pub fn cpu_temperature() -> Option<f64> {
+    let zones = std::fs::read_dir(THERMAL_ZONES_PATH).ok()?;
+
+    zones
+        .filter_map(| entry | entry.ok())
+        .filter(| entry | entry.file_name().to_string_lossy().starts_with("thermal_zone"))
+        .filter_map(| entry | std::fs::read_to_string(entry.path().join("temp")).ok())
+        // Reported in millidegrees.
+        .filter_map(| temp | temp.trim().parse::<f64>().ok())
+        .map(| temp | temp / 1000.0)
+        .reduce(f64::max)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cpu_temperature() -> Option<f64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+const THERMAL_ZONES_PATH: &str = "/sys/class/thermal";